@@ -0,0 +1,22 @@
+use ureq::Agent;
+use ureq::http::Response;
+
+use crate::PrometheusError;
+
+/// HTTP agent reporting error statuses as responses, so their bodies end up in the error.
+pub(crate) fn agent() -> Agent {
+    Agent::config_builder()
+        .http_status_as_error(false)
+        .build()
+        .into()
+}
+
+/// Turns a response with a non-success status into a `PrometheusError::Http`.
+pub(crate) fn check_status(mut response: Response<ureq::Body>) -> Result<(), PrometheusError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body = response.body_mut().read_to_string().unwrap_or_default();
+    Err(PrometheusError::Http(format!("{status}: {}", body.trim())))
+}
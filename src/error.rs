@@ -2,6 +2,8 @@ use std::{fmt::Display, io};
 
 use thiserror::Error;
 
+use crate::MetricType;
+
 /// Error type for Prometheus serialization.
 #[derive(Error, Debug)]
 pub enum PrometheusError {
@@ -11,6 +13,76 @@ pub enum PrometheusError {
     /// Error when serializing.
     #[error("serde internal error: {0}")]
     Custom(String),
+    /// Error when sending metrics over HTTP fails or the server rejects them.
+    #[error("HTTP request failed: {0}")]
+    Http(String),
+    /// Error when a label name is reserved for internal use (starts with `__`).
+    #[error("label `{label}` on metric `{metric}` uses a reserved name")]
+    ReservedLabel {
+        /// Name of the metric carrying the label
+        metric: String,
+        /// The offending label name
+        label: String,
+    },
+    /// Error when a label clashes with a label generated for the metric, such as `le` on a
+    /// histogram, `quantile` on a summary, or a field of an info metric.
+    #[error("label `{label}` on {metric_type} metric `{metric}` clashes with a generated label")]
+    LabelConflict {
+        /// Name of the metric carrying the label
+        metric: String,
+        /// The offending label name
+        label: String,
+        /// Type of the metric
+        metric_type: MetricType,
+    },
+    /// Error when a counter has a negative or NaN value while counter semantics are enforced.
+    #[error("counter `{metric}` has invalid value {value}")]
+    InvalidCounterValue {
+        /// Name of the counter
+        metric: String,
+        /// The offending value
+        value: String,
+    },
+    /// Error when a metric has no descriptor while descriptors are required.
+    #[error("metric `{metric}` has no descriptor")]
+    MissingDescriptor {
+        /// Name of the metric
+        metric: String,
+    },
+    /// Error when a path separator is empty or has characters not valid in metric names.
+    #[error("invalid path separator `{separator}`")]
+    InvalidSeparator {
+        /// The rejected separator
+        separator: String,
+    },
+    /// Error when a metric has an empty path, e.g. a scalar field made transparent by a
+    /// [`Flatten`](crate::Flatten) rule.
+    #[error("metric has an empty path")]
+    EmptyPath,
+    /// Error when metrics merged into one exposition declare the same family with different types.
+    #[error("metric `{metric}` has conflicting types {first} and {second}")]
+    TypeConflict {
+        /// Name of the metric family
+        metric: String,
+        /// Type of the family first serialized
+        first: MetricType,
+        /// Conflicting type
+        second: MetricType,
+    },
+    /// Error when metrics merged into one exposition contain the same series more than once.
+    #[error("series `{series}` is present more than once")]
+    DuplicateSeries {
+        /// Name and labels of the series, e.g. `up{job="api"}`
+        series: String,
+    },
+    /// Error when the rendered output is not well-formed text exposition.
+    #[error("invalid exposition at line {line}: {reason}")]
+    InvalidExposition {
+        /// Line number (starting at 1) of the offending line
+        line: usize,
+        /// What is wrong with the line
+        reason: String,
+    },
 }
 
 impl serde::ser::Error for PrometheusError {
@@ -0,0 +1,222 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::{Ready, ready};
+use std::io::Write;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use http::{HeaderMap, HeaderValue, Request, Response, StatusCode, header};
+use http_body_util::Full;
+use serde::Serialize;
+use tower_service::Service;
+
+use crate::{MetricDescriptor, PrometheusError, PrometheusSerializer, TextFormat};
+
+/// Exposition format negotiated from the `Accept` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Exposition {
+    Text(TextFormat),
+    #[cfg(feature = "protobuf")]
+    Protobuf,
+}
+
+impl Exposition {
+    fn content_type(self) -> &'static str {
+        match self {
+            Exposition::Text(format) => format.content_type(),
+            #[cfg(feature = "protobuf")]
+            Exposition::Protobuf => crate::PROTOBUF_CONTENT_TYPE,
+        }
+    }
+}
+
+/// A [`tower_service::Service`] serving a `/metrics` endpoint, usable with axum
+/// (`Router::route_service`) or hyper.
+///
+/// Each request calls the snapshot closure and serializes the returned value. The exposition
+/// format (Prometheus text, OpenMetrics text, or protobuf with the `protobuf` feature) is
+/// negotiated from the `Accept` header, and the response is gzip-compressed when the client
+/// accepts it.
+pub struct MetricsService<F> {
+    inner: Arc<Inner<F>>,
+}
+
+struct Inner<F> {
+    snapshot: F,
+    namespace: Option<String>,
+    metadata: HashMap<&'static str, MetricDescriptor<'static>>,
+    common_labels: Vec<(&'static str, &'static str)>,
+}
+
+impl<F> Clone for MetricsService<F> {
+    fn clone(&self) -> Self {
+        MetricsService {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<F, T> MetricsService<F>
+where
+    F: Fn() -> T,
+    T: Serialize,
+{
+    /// Create a new service serving the values returned by `snapshot`.
+    pub fn new<L, Li>(
+        namespace: Option<impl Into<String>>,
+        metadata: HashMap<&'static str, MetricDescriptor<'static>>,
+        common_labels: L,
+        snapshot: F,
+    ) -> Self
+    where
+        L: IntoIterator<Item = Li>,
+        Li: Borrow<(&'static str, &'static str)>,
+    {
+        MetricsService {
+            inner: Arc::new(Inner {
+                snapshot,
+                namespace: namespace.map(Into::into),
+                metadata,
+                common_labels: common_labels.into_iter().map(|el| *el.borrow()).collect(),
+            }),
+        }
+    }
+
+    fn render(&self, exposition: Exposition) -> Result<Vec<u8>, PrometheusError> {
+        let inner = &*self.inner;
+        let mut serializer = PrometheusSerializer::new(
+            inner.namespace.as_deref(),
+            &inner.metadata,
+            &inner.common_labels,
+        );
+        let mut buf = Vec::new();
+        match exposition {
+            Exposition::Text(format) => {
+                serializer.set_format(format);
+                (inner.snapshot)().serialize(&mut serializer)?;
+                serializer.finish(&mut buf)?;
+            }
+            #[cfg(feature = "protobuf")]
+            Exposition::Protobuf => {
+                (inner.snapshot)().serialize(&mut serializer)?;
+                serializer.finish_protobuf(&mut buf)?;
+            }
+        }
+        Ok(buf)
+    }
+
+    fn respond(&self, headers: &HeaderMap) -> Response<Full<Bytes>> {
+        let exposition = negotiate(headers);
+        let body = match self.render(exposition) {
+            Ok(body) => body,
+            Err(e) => {
+                let mut response = Response::new(Full::from(e.to_string()));
+                *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                return response;
+            }
+        };
+        let mut response = Response::builder()
+            .header(header::CONTENT_TYPE, exposition.content_type())
+            .header(
+                header::VARY,
+                HeaderValue::from_static("accept, accept-encoding"),
+            );
+        let body = if accepts_gzip(headers) {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            match encoder.write_all(&body).and_then(|()| encoder.finish()) {
+                Ok(compressed) => {
+                    response = response.header(header::CONTENT_ENCODING, "gzip");
+                    compressed
+                }
+                Err(_) => body,
+            }
+        } else {
+            body
+        };
+        response
+            .body(Full::from(body))
+            .unwrap_or_else(|_| Response::new(Full::default()))
+    }
+}
+
+impl<F, T, B> Service<Request<B>> for MetricsService<F>
+where
+    F: Fn() -> T,
+    T: Serialize,
+{
+    type Response = Response<Full<Bytes>>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        ready(Ok(self.respond(request.headers())))
+    }
+}
+
+/// Iterates over the comma-separated entries of a header, yielding each media type or coding
+/// with its parameters and quality value.
+fn header_entries(
+    headers: &HeaderMap,
+    name: header::HeaderName,
+) -> Vec<(String, Vec<String>, f32)> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|entry| {
+            let mut parts = entry.split(';').map(str::trim);
+            let media = parts.next().unwrap_or_default().to_ascii_lowercase();
+            let mut quality = 1.0;
+            let mut params = Vec::new();
+            for param in parts {
+                match param.split_once('=') {
+                    Some(("q", q)) => quality = q.parse().unwrap_or(0.0),
+                    _ => params.push(param.replace(' ', "")),
+                }
+            }
+            (media, params, quality)
+        })
+        .collect()
+}
+
+/// Picks the exposition format with the highest quality value in the `Accept` header, falling
+/// back to the Prometheus text format.
+#[cfg_attr(not(feature = "protobuf"), allow(unused_variables))]
+fn negotiate(headers: &HeaderMap) -> Exposition {
+    let mut best = (Exposition::Text(TextFormat::Prometheus), 0.0);
+    for (media, params, quality) in header_entries(headers, header::ACCEPT) {
+        let exposition = match media.as_str() {
+            #[cfg(feature = "protobuf")]
+            "application/vnd.google.protobuf"
+                if params
+                    .iter()
+                    .any(|p| p == "proto=io.prometheus.client.MetricFamily")
+                    && params.iter().any(|p| p == "encoding=delimited") =>
+            {
+                Exposition::Protobuf
+            }
+            "application/openmetrics-text" => Exposition::Text(TextFormat::OpenMetrics),
+            "text/plain" | "*/*" => Exposition::Text(TextFormat::Prometheus),
+            _ => continue,
+        };
+        if quality > best.1 {
+            best = (exposition, quality);
+        }
+    }
+    best.0
+}
+
+fn accepts_gzip(headers: &HeaderMap) -> bool {
+    header_entries(headers, header::ACCEPT_ENCODING)
+        .iter()
+        .any(|(coding, _, quality)| coding == "gzip" && *quality > 0.0)
+}
@@ -0,0 +1,138 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Serialize, Serializer};
+
+use crate::metrics::add_f64;
+
+/// Newtype struct name marking a serialized [`Histogram`].
+pub(crate) const HISTOGRAM_NAME: &str = "$serde_prom::Histogram";
+
+/// Default bucket upper bounds, suited to request latencies in seconds.
+pub const DEFAULT_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// A histogram recorder counting observations into buckets, backed by atomics.
+///
+/// It serializes as a histogram metric, expanded to `_bucket`, `_sum` and `_count` samples,
+/// without needing a [`MetricDescriptor`](crate::MetricDescriptor). A descriptor with the
+/// [`GaugeHistogram`](crate::MetricType::GaugeHistogram) type turns it into a gauge histogram.
+/// With other serializers, it serializes as its bucket bounds, cumulative counts, sum and count.
+#[derive(Debug)]
+pub struct Histogram {
+    /// Sorted, finite upper bounds of the buckets. The `+Inf` bucket is implicit.
+    bounds: Vec<f64>,
+    /// Number of observations per bucket (not cumulative), the last one being `+Inf`.
+    counts: Vec<AtomicU64>,
+    /// Sum of all observations, stored as `f64` bits.
+    sum: AtomicU64,
+}
+
+#[derive(Serialize)]
+struct HistogramSnapshot<'a> {
+    bounds: &'a [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    /// Create a histogram with the given bucket upper bounds. The bounds are sorted, and
+    /// duplicate and non-finite bounds are ignored.
+    pub fn new(buckets: impl IntoIterator<Item = f64>) -> Self {
+        let mut bounds: Vec<f64> = buckets.into_iter().filter(|b| b.is_finite()).collect();
+        bounds.sort_by(f64::total_cmp);
+        bounds.dedup();
+        Histogram {
+            counts: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            bounds,
+            sum: AtomicU64::new(0.0_f64.to_bits()),
+        }
+    }
+
+    /// Create a histogram with `count` buckets, the first one with upper bound `start` and
+    /// each following one `width` larger.
+    #[must_use]
+    pub fn linear(start: f64, width: f64, count: usize) -> Self {
+        Self::new((0..count).scan(start, |bound, _| {
+            let current = *bound;
+            *bound += width;
+            Some(current)
+        }))
+    }
+
+    /// Create a histogram with `count` buckets, the first one with upper bound `start` and
+    /// each following one `factor` times larger.
+    #[must_use]
+    pub fn exponential(start: f64, factor: f64, count: usize) -> Self {
+        Self::new((0..count).scan(start, |bound, _| {
+            let current = *bound;
+            *bound *= factor;
+            Some(current)
+        }))
+    }
+
+    /// Record an observation. Like in the Go client, NaN observations are only counted in the
+    /// `+Inf` bucket, and make the sum NaN.
+    pub fn observe(&self, value: f64) {
+        let bucket = if value.is_nan() {
+            self.bounds.len()
+        } else {
+            self.bounds.partition_point(|bound| *bound < value)
+        };
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        add_f64(&self.sum, value);
+    }
+
+    /// Upper bounds of the buckets, excluding the implicit `+Inf` bucket.
+    #[must_use]
+    pub fn buckets(&self) -> &[f64] {
+        &self.bounds
+    }
+
+    /// Number of observations.
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.counts.iter().map(|c| c.load(Ordering::Relaxed)).sum()
+    }
+
+    /// Sum of all observations.
+    #[must_use]
+    pub fn sum(&self) -> f64 {
+        f64::from_bits(self.sum.load(Ordering::Relaxed))
+    }
+}
+
+impl Default for Histogram {
+    /// A histogram with the [`DEFAULT_BUCKETS`].
+    fn default() -> Self {
+        Self::new(DEFAULT_BUCKETS)
+    }
+}
+
+impl Serialize for Histogram {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // Count from the buckets so the cumulative counts always add up to the total.
+        let mut count = 0;
+        let mut counts: Vec<u64> = self
+            .counts
+            .iter()
+            .map(|c| {
+                count += c.load(Ordering::Relaxed);
+                count
+            })
+            .collect();
+        // The cumulative count of the `+Inf` bucket is the total count.
+        counts.pop();
+        let snapshot = HistogramSnapshot {
+            bounds: &self.bounds,
+            counts,
+            sum: self.sum(),
+            count,
+        };
+        serializer.serialize_newtype_struct(HISTOGRAM_NAME, &snapshot)
+    }
+}
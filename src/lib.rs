@@ -2,11 +2,49 @@
 #![allow(clippy::doc_markdown)]
 #![allow(clippy::implicit_hasher)]
 pub use error::PrometheusError;
+#[cfg(feature = "http")]
+pub use handler::MetricsService;
+pub use histogram::{DEFAULT_BUCKETS, Histogram};
+pub use metrics::{Counter, FloatGauge, Gauge};
+pub use native::{DEFAULT_ZERO_THRESHOLD, NativeHistogram};
+#[cfg(feature = "protobuf")]
+pub use proto::PROTOBUF_CONTENT_TYPE;
+#[cfg(feature = "pushgateway")]
+pub use pushgateway::Pushgateway;
+pub use registry::Registry;
+#[cfg(feature = "remote-write")]
+pub use remote_write::RemoteWriteClient;
 pub use ser::{
-    MetricDescriptor, MetricType, PrometheusSerializer, to_prometheus_text, write_prometheus_text,
+    Diagnostic, FieldCase, Flatten, LabelSource, MetricDescriptor, MetricType, MetricUnit,
+    MissingDescriptor, NativeFallback, OPENMETRICS_CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE,
+    PrometheusSerializer, TextFormat, Transform, to_prometheus_text, write_prometheus_text,
 };
+#[cfg(feature = "server")]
+pub use server::MetricsServer;
+pub use summary::{DEFAULT_AGE_BUCKETS, DEFAULT_MAX_AGE, DEFAULT_OBJECTIVES, Summary};
+#[cfg(feature = "textfile")]
+pub use textfile::TextfileWriter;
 
+#[cfg(any(feature = "pushgateway", feature = "remote-write"))]
+mod client;
 mod error;
+#[cfg(feature = "http")]
+mod handler;
+mod histogram;
+mod metrics;
+mod native;
+#[cfg(feature = "protobuf")]
+mod proto;
+#[cfg(feature = "pushgateway")]
+mod pushgateway;
+mod registry;
+#[cfg(feature = "remote-write")]
+mod remote_write;
 mod ser;
+#[cfg(feature = "server")]
+mod server;
+mod summary;
 #[cfg(test)]
 mod tests;
+#[cfg(feature = "textfile")]
+mod textfile;
@@ -0,0 +1,156 @@
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use serde::{Serialize, Serializer};
+
+/// Newtype struct name marking a serialized [`Counter`].
+pub(crate) const COUNTER_NAME: &str = "$serde_prom::Counter";
+/// Newtype struct name marking a serialized [`Gauge`] or [`FloatGauge`].
+pub(crate) const GAUGE_NAME: &str = "$serde_prom::Gauge";
+
+/// A monotonically increasing counter backed by an `AtomicU64`.
+///
+/// It serializes as a counter metric without needing a [`MetricDescriptor`](crate::MetricDescriptor),
+/// and as a plain integer with other serializers.
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    /// Create a new counter starting at `value`.
+    #[must_use]
+    pub const fn new(value: u64) -> Self {
+        Counter(AtomicU64::new(value))
+    }
+
+    /// Increment the counter by one.
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    /// Increment the counter by `value`.
+    pub fn inc_by(&self, value: u64) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Current value of the counter.
+    #[must_use]
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Serialize for Counter {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(COUNTER_NAME, &self.get())
+    }
+}
+
+/// An integer gauge backed by an `AtomicI64`.
+///
+/// It serializes as a gauge metric without needing a [`MetricDescriptor`](crate::MetricDescriptor),
+/// and as a plain integer with other serializers.
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    /// Create a new gauge set to `value`.
+    #[must_use]
+    pub const fn new(value: i64) -> Self {
+        Gauge(AtomicI64::new(value))
+    }
+
+    /// Set the gauge to `value`.
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    /// Increment the gauge by one.
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// Decrement the gauge by one.
+    pub fn dec(&self) {
+        self.sub(1);
+    }
+
+    /// Add `value` to the gauge.
+    pub fn add(&self, value: i64) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Subtract `value` from the gauge.
+    pub fn sub(&self, value: i64) {
+        self.0.fetch_sub(value, Ordering::Relaxed);
+    }
+
+    /// Current value of the gauge.
+    #[must_use]
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Serialize for Gauge {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(GAUGE_NAME, &self.get())
+    }
+}
+
+/// A floating-point gauge, stored as the bits of an `f64` in an `AtomicU64`.
+///
+/// It serializes as a gauge metric without needing a [`MetricDescriptor`](crate::MetricDescriptor),
+/// and as a plain float with other serializers.
+#[derive(Debug, Default)]
+pub struct FloatGauge(AtomicU64);
+
+impl FloatGauge {
+    /// Create a new gauge set to `value`.
+    #[must_use]
+    pub fn new(value: f64) -> Self {
+        FloatGauge(AtomicU64::new(value.to_bits()))
+    }
+
+    /// Set the gauge to `value`.
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Add `value` to the gauge.
+    pub fn add(&self, value: f64) {
+        add_f64(&self.0, value);
+    }
+
+    /// Subtract `value` from the gauge.
+    pub fn sub(&self, value: f64) {
+        self.add(-value);
+    }
+
+    /// Current value of the gauge.
+    #[must_use]
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+impl Serialize for FloatGauge {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(GAUGE_NAME, &self.get())
+    }
+}
+
+/// Atomically adds `value` to the `f64` stored as bits in `atomic`.
+pub(crate) fn add_f64(atomic: &AtomicU64, value: f64) {
+    // `fetch_update` only fails when the closure returns `None`.
+    let _ = atomic.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+        Some((f64::from_bits(bits) + value).to_bits())
+    });
+}
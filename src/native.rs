@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+
+use serde::{Serialize, Serializer};
+
+/// Newtype struct name marking a serialized [`NativeHistogram`].
+pub(crate) const NATIVE_HISTOGRAM_NAME: &str = "$serde_prom::NativeHistogram";
+
+/// Default width of the zero bucket, the one used by the Prometheus client libraries.
+pub const DEFAULT_ZERO_THRESHOLD: f64 = 2.938_735_877_055_719e-39;
+
+/// Lowest and highest schemas supported by Prometheus.
+const SCHEMAS: std::ops::RangeInclusive<i32> = -4..=8;
+
+/// A native (sparse, exponential) histogram recorder.
+///
+/// Buckets have exponentially growing boundaries set by the schema: with schema `s`, each
+/// bucket is `2^(2^-s)` times wider than the previous one, so higher schemas have a finer
+/// resolution. Only buckets with observations are stored, and observations whose absolute
+/// value is at most the zero threshold are counted in a single zero bucket.
+///
+/// It serializes as a histogram metric without needing a
+/// [`MetricDescriptor`](crate::MetricDescriptor). The native buckets are only expressible in the
+/// protobuf format (with the `protobuf` feature); text formats fall back to classic buckets, one
+/// per populated native bucket. With other serializers, it serializes as its schema, zero
+/// bucket, bucket indexes and counts, sum and count.
+#[derive(Debug)]
+pub struct NativeHistogram {
+    schema: i32,
+    zero_threshold: f64,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Observation counts of the positive buckets, by index.
+    positive: BTreeMap<i32, u64>,
+    /// Observation counts of the negative buckets, by index of the absolute value.
+    negative: BTreeMap<i32, u64>,
+    zero_count: u64,
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Serialize)]
+struct NativeHistogramSnapshot {
+    schema: i32,
+    zero_threshold: f64,
+    zero_count: u64,
+    positive_indexes: Vec<i32>,
+    positive_counts: Vec<u64>,
+    negative_indexes: Vec<i32>,
+    negative_counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// Sparse buckets of a native histogram, as written to the protobuf format.
+#[derive(Debug)]
+pub(crate) struct NativeBuckets {
+    pub(crate) schema: i32,
+    pub(crate) zero_threshold: f64,
+    pub(crate) zero_count: u64,
+    /// Bucket indexes and counts, sorted by index.
+    pub(crate) positive: Vec<(i32, u64)>,
+    /// Bucket indexes and counts, sorted by index.
+    pub(crate) negative: Vec<(i32, u64)>,
+}
+
+impl NativeHistogram {
+    /// Create a native histogram with the given schema, clamped to Prometheus' `-4..=8`, and the
+    /// [`DEFAULT_ZERO_THRESHOLD`].
+    #[must_use]
+    pub fn new(schema: i32) -> Self {
+        Self::with_zero_threshold(schema, DEFAULT_ZERO_THRESHOLD)
+    }
+
+    /// Create a native histogram with the given schema and zero bucket width.
+    #[must_use]
+    pub fn with_zero_threshold(schema: i32, zero_threshold: f64) -> Self {
+        NativeHistogram {
+            schema: schema.clamp(*SCHEMAS.start(), *SCHEMAS.end()),
+            zero_threshold: zero_threshold.abs(),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Record an observation. NaN observations only count towards the sum and count.
+    pub fn observe(&self, value: f64) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if value.abs() <= self.zero_threshold {
+            state.zero_count += 1;
+        } else if value > 0.0 {
+            *state
+                .positive
+                .entry(bucket_index(value, self.schema))
+                .or_default() += 1;
+        } else if value < 0.0 {
+            *state
+                .negative
+                .entry(bucket_index(-value, self.schema))
+                .or_default() += 1;
+        }
+        state.sum += value;
+        state.count += 1;
+    }
+
+    /// Schema of the histogram.
+    #[must_use]
+    pub fn schema(&self) -> i32 {
+        self.schema
+    }
+
+    /// Number of observations.
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .count
+    }
+
+    /// Sum of all observations.
+    #[must_use]
+    pub fn sum(&self) -> f64 {
+        self.state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .sum
+    }
+}
+
+impl Serialize for NativeHistogram {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let snapshot = {
+            let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            let (positive_indexes, positive_counts) = state.positive.iter().unzip();
+            let (negative_indexes, negative_counts) = state.negative.iter().unzip();
+            NativeHistogramSnapshot {
+                schema: self.schema,
+                zero_threshold: self.zero_threshold,
+                zero_count: state.zero_count,
+                positive_indexes,
+                positive_counts,
+                negative_indexes,
+                negative_counts,
+                sum: state.sum,
+                count: state.count,
+            }
+        };
+        serializer.serialize_newtype_struct(NATIVE_HISTOGRAM_NAME, &snapshot)
+    }
+}
+
+impl NativeBuckets {
+    /// Classic cumulative buckets equivalent to the native ones, as upper bounds and counts
+    /// sorted by upper bound, excluding the `+Inf` bucket.
+    pub(crate) fn classic_buckets(&self) -> Vec<(f64, u64)> {
+        let mut buckets = Vec::new();
+        let mut cumulative = 0;
+        // The negative bucket with index `i` is `[-upper_bound(i), -upper_bound(i - 1))`.
+        for &(index, count) in self.negative.iter().rev() {
+            cumulative += count;
+            buckets.push((-upper_bound(index - 1, self.schema), cumulative));
+        }
+        if self.zero_count > 0 {
+            cumulative += self.zero_count;
+            buckets.push((self.zero_threshold, cumulative));
+        }
+        for &(index, count) in &self.positive {
+            cumulative += count;
+            buckets.push((upper_bound(index, self.schema), cumulative));
+        }
+        buckets
+    }
+}
+
+/// Upper bound of the bucket with the given index: `2^(index * 2^-schema)`.
+fn upper_bound(index: i32, schema: i32) -> f64 {
+    (f64::from(index) * 2_f64.powi(-schema)).exp2()
+}
+
+/// Index of the bucket `(upper_bound(index - 1), upper_bound(index)]` containing the positive
+/// `value`.
+#[allow(clippy::cast_possible_truncation)]
+fn bucket_index(value: f64, schema: i32) -> i32 {
+    let estimate = (value.log2() * 2_f64.powi(schema)).ceil();
+    let mut index = estimate.clamp(f64::from(i32::MIN), f64::from(i32::MAX)) as i32;
+    // Correct rounding errors of the logarithm near bucket boundaries.
+    if upper_bound(index, schema) < value {
+        index += 1;
+    } else if upper_bound(index - 1, schema) >= value {
+        index -= 1;
+    }
+    index
+}
@@ -0,0 +1,291 @@
+use std::io;
+
+use indexmap::IndexMap;
+use prost::Message;
+
+use crate::PrometheusError;
+use crate::native::NativeBuckets;
+use crate::ser::{MetricFamily, MetricType, PrometheusSerializer};
+
+/// Content type of the length-delimited Prometheus protobuf exposition format.
+pub const PROTOBUF_CONTENT_TYPE: &str =
+    "application/vnd.google.protobuf; proto=io.prometheus.client.MetricFamily; encoding=delimited";
+
+// Messages from `io.prometheus.client` (prometheus/client_model `metrics.proto`).
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct LabelPair {
+    #[prost(string, tag = "1")]
+    pub(crate) name: String,
+    #[prost(string, tag = "2")]
+    pub(crate) value: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub(crate) enum ProtoMetricType {
+    Counter = 0,
+    Gauge = 1,
+    Summary = 2,
+    Untyped = 3,
+    Histogram = 4,
+    GaugeHistogram = 5,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct Gauge {
+    #[prost(double, tag = "1")]
+    pub(crate) value: f64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct Counter {
+    #[prost(double, tag = "1")]
+    pub(crate) value: f64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct Quantile {
+    #[prost(double, tag = "1")]
+    pub(crate) quantile: f64,
+    #[prost(double, tag = "2")]
+    pub(crate) value: f64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct Summary {
+    #[prost(uint64, tag = "1")]
+    pub(crate) sample_count: u64,
+    #[prost(double, tag = "2")]
+    pub(crate) sample_sum: f64,
+    #[prost(message, repeated, tag = "3")]
+    pub(crate) quantile: Vec<Quantile>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct Untyped {
+    #[prost(double, tag = "1")]
+    pub(crate) value: f64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct Histogram {
+    #[prost(uint64, tag = "1")]
+    pub(crate) sample_count: u64,
+    #[prost(double, tag = "2")]
+    pub(crate) sample_sum: f64,
+    #[prost(message, repeated, tag = "3")]
+    pub(crate) bucket: Vec<Bucket>,
+    #[prost(sint32, tag = "5")]
+    pub(crate) schema: i32,
+    #[prost(double, tag = "6")]
+    pub(crate) zero_threshold: f64,
+    #[prost(uint64, tag = "7")]
+    pub(crate) zero_count: u64,
+    #[prost(message, repeated, tag = "9")]
+    pub(crate) negative_span: Vec<BucketSpan>,
+    #[prost(sint64, repeated, tag = "10")]
+    pub(crate) negative_delta: Vec<i64>,
+    #[prost(message, repeated, tag = "12")]
+    pub(crate) positive_span: Vec<BucketSpan>,
+    #[prost(sint64, repeated, tag = "13")]
+    pub(crate) positive_delta: Vec<i64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct Bucket {
+    #[prost(uint64, tag = "1")]
+    pub(crate) cumulative_count: u64,
+    #[prost(double, tag = "2")]
+    pub(crate) upper_bound: f64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct BucketSpan {
+    #[prost(sint32, tag = "1")]
+    pub(crate) offset: i32,
+    #[prost(uint32, tag = "2")]
+    pub(crate) length: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct Metric {
+    #[prost(message, repeated, tag = "1")]
+    pub(crate) label: Vec<LabelPair>,
+    #[prost(message, optional, tag = "2")]
+    pub(crate) gauge: Option<Gauge>,
+    #[prost(message, optional, tag = "3")]
+    pub(crate) counter: Option<Counter>,
+    #[prost(message, optional, tag = "4")]
+    pub(crate) summary: Option<Summary>,
+    #[prost(message, optional, tag = "5")]
+    pub(crate) untyped: Option<Untyped>,
+    #[prost(message, optional, tag = "7")]
+    pub(crate) histogram: Option<Histogram>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct ProtoMetricFamily {
+    #[prost(string, tag = "1")]
+    pub(crate) name: String,
+    #[prost(string, tag = "2")]
+    pub(crate) help: String,
+    #[prost(enumeration = "ProtoMetricType", tag = "3")]
+    pub(crate) r#type: i32,
+    #[prost(message, repeated, tag = "4")]
+    pub(crate) metric: Vec<Metric>,
+    #[prost(string, tag = "5")]
+    pub(crate) unit: String,
+}
+
+impl PrometheusSerializer<'_> {
+    /// Finalizes the serializer by writing all buffered metric families in the Prometheus
+    /// protobuf format, as length-delimited `io.prometheus.client.MetricFamily` messages.
+    ///
+    /// # Errors
+    /// Returns a `PrometheusError` if writing to the output stream fails.
+    pub fn finish_protobuf<W>(self, mut output: W) -> Result<(), PrometheusError>
+    where
+        W: io::Write,
+    {
+        for family in self.into_families().values() {
+            output.write_all(&encode_family(family).encode_length_delimited_to_vec())?;
+        }
+        Ok(())
+    }
+}
+
+/// Converts a buffered family into its protobuf message. Histogram and summary samples are
+/// grouped into one metric per label set, ignoring the `le` and `quantile` labels.
+pub(crate) fn encode_family(family: &MetricFamily) -> ProtoMetricFamily {
+    let proto_type = match family.metric_type {
+        MetricType::Counter => ProtoMetricType::Counter,
+        MetricType::Gauge | MetricType::Info | MetricType::StateSet => ProtoMetricType::Gauge,
+        MetricType::Summary => ProtoMetricType::Summary,
+        MetricType::Histogram => ProtoMetricType::Histogram,
+        MetricType::GaugeHistogram => ProtoMetricType::GaugeHistogram,
+        MetricType::Untyped | MetricType::Unknown => ProtoMetricType::Untyped,
+    };
+    let mut metrics: IndexMap<Vec<(String, String)>, Metric> = IndexMap::new();
+    for sample in family.samples.values() {
+        let suffix = sample.name.strip_prefix(&family.name).unwrap_or_default();
+        let value = parse_value(&sample.value);
+        let group_label = match proto_type {
+            ProtoMetricType::Histogram | ProtoMetricType::GaugeHistogram => Some("le"),
+            ProtoMetricType::Summary => Some("quantile"),
+            _ => None,
+        };
+        let mut group_value = None;
+        let labels: Vec<(String, String)> = sample
+            .labels
+            .iter()
+            .filter(|(k, v)| {
+                if Some(k.as_str()) == group_label {
+                    group_value = Some(parse_value(v));
+                    false
+                } else {
+                    true
+                }
+            })
+            .cloned()
+            .collect();
+        let metric = metrics.entry(labels).or_insert_with_key(|labels| Metric {
+            label: labels
+                .iter()
+                .map(|(name, value)| LabelPair {
+                    name: name.clone(),
+                    value: value.clone(),
+                })
+                .collect(),
+            ..Default::default()
+        });
+        match proto_type {
+            ProtoMetricType::Counter => metric.counter = Some(Counter { value }),
+            ProtoMetricType::Gauge => metric.gauge = Some(Gauge { value }),
+            ProtoMetricType::Untyped => metric.untyped = Some(Untyped { value }),
+            ProtoMetricType::Histogram | ProtoMetricType::GaugeHistogram => {
+                let histogram = metric.histogram.get_or_insert_default();
+                match (suffix, group_value) {
+                    ("_bucket", Some(upper_bound)) => histogram.bucket.push(Bucket {
+                        cumulative_count: to_count(value),
+                        upper_bound,
+                    }),
+                    ("_sum" | "_gsum", _) => histogram.sample_sum = value,
+                    ("_count" | "_gcount", _) => {
+                        histogram.sample_count = to_count(value);
+                        if let Some(native) = &sample.native {
+                            // The classic buckets of a native histogram are derived from
+                            // its populated buckets, so their bounds change between scrapes.
+                            histogram.bucket.clear();
+                            encode_native(histogram, native);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            ProtoMetricType::Summary => {
+                let summary = metric.summary.get_or_insert_default();
+                match (suffix, group_value) {
+                    ("", Some(quantile)) => summary.quantile.push(Quantile { quantile, value }),
+                    ("_sum", _) => summary.sample_sum = value,
+                    ("_count", _) => summary.sample_count = to_count(value),
+                    _ => {}
+                }
+            }
+        }
+    }
+    ProtoMetricFamily {
+        name: family.name.clone(),
+        help: family.help.clone(),
+        r#type: proto_type.into(),
+        metric: metrics.into_values().collect(),
+        unit: family.unit.map(|unit| unit.to_string()).unwrap_or_default(),
+    }
+}
+
+/// Sets the native buckets of a histogram, as spans of consecutive bucket indexes and the
+/// differences between consecutive bucket counts.
+fn encode_native(histogram: &mut Histogram, native: &NativeBuckets) {
+    histogram.schema = native.schema;
+    histogram.zero_threshold = native.zero_threshold;
+    histogram.zero_count = native.zero_count;
+    (histogram.positive_span, histogram.positive_delta) = spans_and_deltas(&native.positive);
+    (histogram.negative_span, histogram.negative_delta) = spans_and_deltas(&native.negative);
+    if histogram.positive_span.is_empty()
+        && histogram.negative_span.is_empty()
+        && histogram.zero_count == 0
+    {
+        // An empty span marks the histogram as native even without observations.
+        histogram.positive_span.push(BucketSpan::default());
+    }
+}
+
+#[allow(clippy::cast_possible_wrap)]
+fn spans_and_deltas(buckets: &[(i32, u64)]) -> (Vec<BucketSpan>, Vec<i64>) {
+    let mut spans: Vec<BucketSpan> = Vec::new();
+    let mut deltas = Vec::with_capacity(buckets.len());
+    let mut next_index = None;
+    let mut previous_count = 0;
+    for &(index, count) in buckets {
+        match (next_index, spans.last_mut()) {
+            (Some(next), Some(span)) if next == index => span.length += 1,
+            _ => spans.push(BucketSpan {
+                offset: next_index.map_or(index, |next| index - next),
+                length: 1,
+            }),
+        }
+        next_index = Some(index + 1);
+        deltas.push(count as i64 - previous_count as i64);
+        previous_count = count;
+    }
+    (spans, deltas)
+}
+
+fn parse_value(value: &str) -> f64 {
+    value.parse().unwrap_or(f64::NAN)
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn to_count(value: f64) -> u64 {
+    value as u64
+}
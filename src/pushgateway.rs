@@ -0,0 +1,135 @@
+use std::fmt::Write as _;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE;
+use ureq::Agent;
+
+use crate::client::{agent, check_status};
+use crate::{PROMETHEUS_CONTENT_TYPE, PrometheusError, PrometheusSerializer, TextFormat};
+
+/// HTTP method of a push, which decides how existing metrics in the group are treated.
+#[derive(Debug, Clone, Copy)]
+enum PushMethod {
+    /// `PUT`: replace all metrics of the group
+    Put,
+    /// `POST`: replace only metrics with the same name
+    Post,
+}
+
+/// Client for a Prometheus [Pushgateway](https://github.com/prometheus/pushgateway), for batch
+/// jobs that can't be scraped.
+///
+/// Metrics are pushed to the group identified by the job name and the grouping labels, i.e.
+/// `/metrics/job/<job>/<label>/<value>/...`.
+pub struct Pushgateway {
+    agent: Agent,
+    base_url: String,
+    job: String,
+    grouping_labels: Vec<(String, String)>,
+}
+
+impl Pushgateway {
+    /// Create a new client for the Pushgateway at `base_url` (e.g. `http://localhost:9091`),
+    /// pushing to the group of `job`. `https` URLs need the `tls` feature.
+    pub fn new(base_url: impl Into<String>, job: impl Into<String>) -> Self {
+        Pushgateway {
+            agent: agent(),
+            base_url: base_url.into().trim_end_matches('/').to_owned(),
+            job: job.into(),
+            grouping_labels: Vec::new(),
+        }
+    }
+
+    /// Set the grouping labels identifying the group in addition to the job name.
+    pub fn set_grouping_labels<L>(&mut self, labels: L)
+    where
+        L: IntoIterator<Item = (String, String)>,
+    {
+        self.grouping_labels = labels.into_iter().collect();
+    }
+
+    /// URL of the group, with the job name and grouping labels encoded as path segments.
+    /// Values containing `/` (and empty values) are base64-encoded as required by the
+    /// Pushgateway.
+    #[must_use]
+    pub fn url(&self) -> String {
+        let mut url = format!("{}/metrics", self.base_url);
+        for (name, value) in std::iter::once(("job", self.job.as_str())).chain(
+            self.grouping_labels
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        ) {
+            if value.is_empty() || value.contains('/') {
+                let encoded = URL_SAFE.encode(value);
+                let encoded = if encoded.is_empty() { "=" } else { &encoded };
+                let _ = write!(url, "/{name}@base64/{encoded}");
+            } else {
+                let _ = write!(url, "/{name}/{}", encode_path_segment(value));
+            }
+        }
+        url
+    }
+
+    /// Push the metrics of the serializer, replacing all metrics in the group (`PUT`).
+    ///
+    /// # Errors
+    /// Returns a `PrometheusError` if the request fails or the Pushgateway rejects it.
+    pub fn push(&self, serializer: PrometheusSerializer<'_>) -> Result<(), PrometheusError> {
+        self.send(PushMethod::Put, serializer)
+    }
+
+    /// Push the metrics of the serializer, replacing only metrics with the same names in the
+    /// group (`POST`).
+    ///
+    /// # Errors
+    /// Returns a `PrometheusError` if the request fails or the Pushgateway rejects it.
+    pub fn push_add(&self, serializer: PrometheusSerializer<'_>) -> Result<(), PrometheusError> {
+        self.send(PushMethod::Post, serializer)
+    }
+
+    /// Delete all metrics in the group (`DELETE`).
+    ///
+    /// # Errors
+    /// Returns a `PrometheusError` if the request fails or the Pushgateway rejects it.
+    pub fn delete(&self) -> Result<(), PrometheusError> {
+        let response = self
+            .agent
+            .delete(self.url())
+            .call()
+            .map_err(|e| PrometheusError::Http(e.to_string()))?;
+        check_status(response)
+    }
+
+    fn send(
+        &self,
+        method: PushMethod,
+        mut serializer: PrometheusSerializer<'_>,
+    ) -> Result<(), PrometheusError> {
+        // The Pushgateway only accepts the Prometheus text format sent with its content type.
+        serializer.set_format(TextFormat::Prometheus);
+        let mut body = Vec::new();
+        serializer.finish(&mut body)?;
+        let request = match method {
+            PushMethod::Put => self.agent.put(self.url()),
+            PushMethod::Post => self.agent.post(self.url()),
+        };
+        let response = request
+            .header("Content-Type", PROMETHEUS_CONTENT_TYPE)
+            .send(&body[..])
+            .map_err(|e| PrometheusError::Http(e.to_string()))?;
+        check_status(response)
+    }
+}
+
+/// Percent-encodes everything but unreserved characters in a URL path segment.
+fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
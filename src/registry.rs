@@ -0,0 +1,96 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use serde::Serialize;
+
+use crate::{MetricDescriptor, PrometheusError, PrometheusSerializer};
+
+/// Metadata of the serializer holding the merged families, which are already described.
+static NO_METADATA: LazyLock<HashMap<&'static str, MetricDescriptor<'static>>> =
+    LazyLock::new(HashMap::new);
+
+type Snapshot =
+    Box<dyn Fn(&mut PrometheusSerializer<'_>) -> Result<(), PrometheusError> + Send + Sync>;
+
+/// A source of metrics registered in a [`Registry`].
+struct Source {
+    name: String,
+    namespace: Option<String>,
+    metadata: HashMap<&'static str, MetricDescriptor<'static>>,
+    common_labels: Vec<(&'static str, &'static str)>,
+    snapshot: Snapshot,
+}
+
+/// A collection of independently owned metric sources rendered into a single exposition.
+///
+/// Each source is serialized with its own namespace, descriptors and common labels. Families
+/// with the same name across sources are merged, so each family gets a single header.
+#[derive(Default)]
+pub struct Registry {
+    sources: Vec<Source>,
+}
+
+impl Registry {
+    /// Create an empty registry.
+    #[must_use]
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    /// Register a source serializing the values returned by `snapshot`. A source previously
+    /// registered under the same name is replaced.
+    pub fn register<F, T, L, Li>(
+        &mut self,
+        name: impl Into<String>,
+        namespace: Option<impl Into<String>>,
+        metadata: HashMap<&'static str, MetricDescriptor<'static>>,
+        common_labels: L,
+        snapshot: F,
+    ) where
+        F: Fn() -> T + Send + Sync + 'static,
+        T: Serialize,
+        L: IntoIterator<Item = Li>,
+        Li: Borrow<(&'static str, &'static str)>,
+    {
+        let source = Source {
+            name: name.into(),
+            namespace: namespace.map(Into::into),
+            metadata,
+            common_labels: common_labels.into_iter().map(|el| *el.borrow()).collect(),
+            snapshot: Box::new(move |serializer| snapshot().serialize(serializer)),
+        };
+        match self.sources.iter_mut().find(|s| s.name == source.name) {
+            Some(existing) => *existing = source,
+            None => self.sources.push(source),
+        }
+    }
+
+    /// Remove the source registered under `name`. Returns whether it was registered.
+    pub fn unregister(&mut self, name: &str) -> bool {
+        let len = self.sources.len();
+        self.sources.retain(|s| s.name != name);
+        self.sources.len() != len
+    }
+
+    /// Snapshot all sources, in registration order, into a single serializer. Call one of its
+    /// `finish` methods to write the exposition.
+    ///
+    /// # Errors
+    /// Returns a `PrometheusError` if serializing a source fails, if sources declare the same
+    /// metric family with different types, or if they write the same series.
+    pub fn gather(&self) -> Result<PrometheusSerializer<'static>, PrometheusError> {
+        let mut merged =
+            PrometheusSerializer::new(None::<&str>, &NO_METADATA, [] as [(&str, &str); 0]);
+        for source in &self.sources {
+            let mut serializer = PrometheusSerializer::new(
+                source.namespace.as_deref(),
+                &source.metadata,
+                &source.common_labels,
+            );
+            (source.snapshot)(&mut serializer)?;
+            merged.extend(serializer)?;
+        }
+        Ok(merged)
+    }
+}
@@ -0,0 +1,157 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use prost::Message;
+use ureq::Agent;
+
+use crate::PrometheusError;
+use crate::client::{agent, check_status};
+use crate::ser::{MetricType, PrometheusSerializer};
+
+/// Content type of a remote-write request body.
+const REMOTE_WRITE_CONTENT_TYPE: &str = "application/x-protobuf";
+/// Version of the remote-write protocol implemented.
+const REMOTE_WRITE_VERSION: &str = "0.1.0";
+
+// Messages from `prometheus.WriteRequest` (prometheus/prompb `remote.proto` and `types.proto`).
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub(crate) timeseries: Vec<TimeSeries>,
+    #[prost(message, repeated, tag = "3")]
+    pub(crate) metadata: Vec<MetricMetadata>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    pub(crate) labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    pub(crate) samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct Label {
+    #[prost(string, tag = "1")]
+    pub(crate) name: String,
+    #[prost(string, tag = "2")]
+    pub(crate) value: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct Sample {
+    #[prost(double, tag = "1")]
+    pub(crate) value: f64,
+    #[prost(int64, tag = "2")]
+    pub(crate) timestamp: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub(crate) enum RemoteMetricType {
+    Unknown = 0,
+    Counter = 1,
+    Gauge = 2,
+    Histogram = 3,
+    GaugeHistogram = 4,
+    Summary = 5,
+    Info = 6,
+    StateSet = 7,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct MetricMetadata {
+    #[prost(enumeration = "RemoteMetricType", tag = "1")]
+    pub(crate) r#type: i32,
+    #[prost(string, tag = "2")]
+    pub(crate) metric_family_name: String,
+    #[prost(string, tag = "4")]
+    pub(crate) help: String,
+    #[prost(string, tag = "5")]
+    pub(crate) unit: String,
+}
+
+impl PrometheusSerializer<'_> {
+    /// Finalizes the serializer by converting all buffered samples into a Prometheus
+    /// remote-write `WriteRequest`, each sample stamped with `timestamp`. Returns the
+    /// Snappy-compressed protobuf message, ready to be sent to a remote-write receiver.
+    ///
+    /// # Errors
+    /// Returns a `PrometheusError` if compressing the message fails.
+    pub fn finish_remote_write(self, timestamp: SystemTime) -> Result<Vec<u8>, PrometheusError> {
+        let timestamp = timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| i64::try_from(t.as_millis()).unwrap_or(i64::MAX));
+        let mut request = WriteRequest::default();
+        for family in self.into_families().into_values() {
+            let metric_type = match family.metric_type {
+                MetricType::Untyped | MetricType::Unknown => RemoteMetricType::Unknown,
+                MetricType::Counter => RemoteMetricType::Counter,
+                MetricType::Gauge => RemoteMetricType::Gauge,
+                MetricType::Histogram => RemoteMetricType::Histogram,
+                MetricType::GaugeHistogram => RemoteMetricType::GaugeHistogram,
+                MetricType::Summary => RemoteMetricType::Summary,
+                MetricType::Info => RemoteMetricType::Info,
+                MetricType::StateSet => RemoteMetricType::StateSet,
+            };
+            request.metadata.push(MetricMetadata {
+                r#type: metric_type.into(),
+                metric_family_name: family.name,
+                help: family.help,
+                unit: family.unit.map(|unit| unit.to_string()).unwrap_or_default(),
+            });
+            for sample in family.samples.into_values() {
+                // Receivers require labels sorted by name.
+                let mut labels: Vec<Label> = std::iter::once(("__name__".to_owned(), sample.name))
+                    .chain(sample.labels)
+                    .map(|(name, value)| Label { name, value })
+                    .collect();
+                labels.sort_by(|a, b| a.name.cmp(&b.name));
+                request.timeseries.push(TimeSeries {
+                    labels,
+                    samples: vec![Sample {
+                        value: sample.value.parse().unwrap_or(f64::NAN),
+                        timestamp,
+                    }],
+                });
+            }
+        }
+        snap::raw::Encoder::new()
+            .compress_vec(&request.encode_to_vec())
+            .map_err(|e| PrometheusError::Custom(e.to_string()))
+    }
+}
+
+/// Client sending metrics to a Prometheus remote-write receiver.
+pub struct RemoteWriteClient {
+    agent: Agent,
+    url: String,
+}
+
+impl RemoteWriteClient {
+    /// Create a new client for the remote-write endpoint at `url`
+    /// (e.g. `http://localhost:9090/api/v1/write`). `https` URLs need the `tls` feature.
+    pub fn new(url: impl Into<String>) -> Self {
+        RemoteWriteClient {
+            agent: agent(),
+            url: url.into(),
+        }
+    }
+
+    /// Send the metrics of the serializer, stamped with the current time.
+    ///
+    /// # Errors
+    /// Returns a `PrometheusError` if the request fails or the receiver rejects it.
+    pub fn send(&self, serializer: PrometheusSerializer<'_>) -> Result<(), PrometheusError> {
+        let body = serializer.finish_remote_write(SystemTime::now())?;
+        let response = self
+            .agent
+            .post(&self.url)
+            .header("Content-Type", REMOTE_WRITE_CONTENT_TYPE)
+            .header("Content-Encoding", "snappy")
+            .header("X-Prometheus-Remote-Write-Version", REMOTE_WRITE_VERSION)
+            .send(&body[..])
+            .map_err(|e| PrometheusError::Http(e.to_string()))?;
+        check_status(response)
+    }
+}
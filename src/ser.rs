@@ -1,18 +1,26 @@
 use super::error::PrometheusError;
+use crate::histogram::HISTOGRAM_NAME;
+use crate::metrics::{COUNTER_NAME, GAUGE_NAME};
+use crate::native::{NATIVE_HISTOGRAM_NAME, NativeBuckets};
+use crate::summary::SUMMARY_NAME;
 
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
 use serde::Serialize;
 use serde::ser::{
     SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
     SerializeTupleStruct, SerializeTupleVariant, Serializer,
 };
-use std::borrow::Borrow;
-use std::collections::HashMap;
+use std::borrow::{Borrow, Cow};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::io::{self, Cursor};
-use strum_macros::{AsRefStr, Display as DisplayStr, EnumString};
+use std::time::Duration;
+use strum_macros::{AsRefStr, Display as DisplayStr, EnumString, IntoStaticStr};
 
 /// Metric type (counter, gauge, histogram, summary, etc.)
-#[derive(Debug, Clone, Copy, EnumString, AsRefStr, DisplayStr, Default, PartialEq, Eq)]
+#[derive(
+    Debug, Clone, Copy, EnumString, AsRefStr, IntoStaticStr, DisplayStr, Default, PartialEq, Eq,
+)]
 #[strum(serialize_all = "snake_case")]
 pub enum MetricType {
     /// Untyped metric (default)
@@ -26,6 +34,93 @@ pub enum MetricType {
     Histogram,
     /// Summary metric
     Summary,
+    /// Info metric (OpenMetrics). Fields of the value become labels of a single sample.
+    Info,
+    /// State set metric (OpenMetrics). Each state becomes a sample labelled with the state name.
+    #[strum(serialize = "stateset")]
+    StateSet,
+    /// Gauge histogram metric (OpenMetrics)
+    #[strum(serialize = "gaugehistogram")]
+    GaugeHistogram,
+    /// Unknown metric (OpenMetrics equivalent of untyped)
+    Unknown,
+}
+
+impl MetricType {
+    /// Type name used in the `# TYPE` line for the given text format.
+    ///
+    /// Types that only exist in OpenMetrics are mapped to their closest classic Prometheus
+    /// equivalent, and `untyped` is written as `unknown` in OpenMetrics.
+    #[must_use]
+    pub fn type_name(self, format: TextFormat) -> &'static str {
+        match (format, self) {
+            (TextFormat::Prometheus, Self::Unknown) => "untyped",
+            (TextFormat::Prometheus, Self::Info | Self::StateSet) => "gauge",
+            (TextFormat::Prometheus, Self::GaugeHistogram) => "histogram",
+            (TextFormat::OpenMetrics, Self::Untyped) => "unknown",
+            _ => self.into(),
+        }
+    }
+
+    /// Label generated for the samples of the type, such as `le` for histogram buckets.
+    pub(crate) fn generated_label(self) -> Option<&'static str> {
+        match self {
+            Self::Histogram | Self::GaugeHistogram => Some("le"),
+            Self::Summary => Some("quantile"),
+            _ => None,
+        }
+    }
+}
+
+/// Base unit of a metric, appended to its name and exposed in OpenMetrics as `# UNIT`.
+#[derive(Debug, Clone, Copy, EnumString, AsRefStr, IntoStaticStr, DisplayStr, PartialEq, Eq)]
+#[strum(serialize_all = "snake_case")]
+pub enum MetricUnit {
+    /// Seconds
+    Seconds,
+    /// Bytes
+    Bytes,
+    /// Ratio (0 to 1)
+    Ratio,
+    /// Degrees Celsius
+    Celsius,
+    /// Meters
+    Meters,
+    /// Grams
+    Grams,
+    /// Volts
+    Volts,
+    /// Amperes
+    Amperes,
+    /// Joules
+    Joules,
+}
+
+/// Content type of the classic Prometheus text format.
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+/// Content type of the OpenMetrics text format.
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Text exposition format written by [`PrometheusSerializer::finish`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextFormat {
+    /// Classic Prometheus text format (version 0.0.4)
+    #[default]
+    Prometheus,
+    /// OpenMetrics text format (version 1.0.0)
+    OpenMetrics,
+}
+
+impl TextFormat {
+    /// HTTP `Content-Type` of the format.
+    #[must_use]
+    pub fn content_type(self) -> &'static str {
+        match self {
+            TextFormat::Prometheus => PROMETHEUS_CONTENT_TYPE,
+            TextFormat::OpenMetrics => OPENMETRICS_CONTENT_TYPE,
+        }
+    }
 }
 
 /// Metadata for each metric, including type, help text, and optional custom labels.
@@ -39,18 +134,263 @@ pub struct MetricDescriptor<'s> {
     pub labels: Vec<(&'s str, &'s str)>,
     /// Optional custom name for the metric
     pub rename: Option<&'s str>,
+    /// Inherited (current or common) label names to drop from this metric
+    pub drop_labels: Vec<&'s str>,
+    /// Optional unit, appended to the metric name if it doesn't already end with it
+    pub unit: Option<MetricUnit>,
+    /// Transforms applied in order to the value before it is written, e.g. to convert
+    /// milliseconds to seconds
+    pub transforms: Vec<Transform>,
+    /// Skip this metric entirely
+    pub skip: bool,
+    /// How the field name adds to the path of a nested struct's metrics
+    pub flatten: Flatten<'s>,
+}
+
+/// How the name of a field adds to the path of the metrics nested in it. Writing a metric whose
+/// path ends up empty, e.g. a top-level scalar field made transparent, fails with
+/// [`PrometheusError::EmptyPath`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Flatten<'s> {
+    /// Append the field name to the path
+    #[default]
+    Nested,
+    /// Keep the path of the parent, like `#[serde(flatten)]`
+    Transparent,
+    /// Append the given name instead of the field name
+    Replace(&'s str),
+    /// Start a new path with the given name, dropping the path of the parent. An empty name
+    /// makes the nested metrics top-level.
+    Root(&'s str),
+}
+
+/// Transform of a numeric value, applied before the value is written.
+#[derive(Debug, Clone, Copy)]
+pub enum Transform {
+    /// Multiply the value by a factor, e.g. `0.001` to convert milliseconds to seconds, `1024`
+    /// to convert KiB to bytes, or `0.01` to convert a percentage to a ratio
+    Scale(f64),
+    /// Replace the value with its reciprocal, e.g. to convert a frequency to a period
+    Invert,
+    /// Limit the value to the range from the minimum to the maximum
+    Clamp(f64, f64),
+    /// Apply a custom function
+    Custom(fn(f64) -> f64),
+}
+
+impl Transform {
+    /// Applies the transform to `value`.
+    #[must_use]
+    pub fn apply(self, value: f64) -> f64 {
+        match self {
+            Transform::Scale(factor) => value * factor,
+            Transform::Invert => value.recip(),
+            Transform::Clamp(min, max) => value.max(min).min(max),
+            Transform::Custom(f) => f(value),
+        }
+    }
+}
+
+/// A problem found by [`PrometheusSerializer::lint`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// A descriptor that didn't match any metric
+    UnusedDescriptor {
+        /// Key of the descriptor in the metadata
+        key: String,
+    },
+    /// A metric without a descriptor
+    Undescribed {
+        /// Path of the metric
+        path: String,
+    },
+    /// Metrics at different paths written under the same name, e.g. because of a rename
+    NameCollision {
+        /// Name of the metric
+        metric: String,
+        /// Paths of the colliding metrics
+        paths: Vec<String>,
+    },
+    /// Distinct map keys written at the same path once sanitized, e.g. `GET x` and `GET.x`,
+    /// whose values overwrite each other
+    KeyCollision {
+        /// Path of the metrics
+        path: String,
+        /// The colliding keys
+        keys: Vec<String>,
+    },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::UnusedDescriptor { key } => {
+                write!(f, "descriptor `{key}` doesn't match any metric")
+            }
+            Diagnostic::Undescribed { path } => write!(f, "metric `{path}` has no descriptor"),
+            Diagnostic::NameCollision { metric, paths } => {
+                write!(
+                    f,
+                    "metric `{metric}` is written by `{}`",
+                    paths.join("`, `")
+                )
+            }
+            Diagnostic::KeyCollision { path, keys } => {
+                write!(
+                    f,
+                    "map keys `{}` are all written at `{path}`",
+                    keys.join("`, `")
+                )
+            }
+        }
+    }
+}
+
+/// Case conversion applied to the field names of a path.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FieldCase {
+    /// Keep field names as they are
+    #[default]
+    Preserve,
+    /// Convert camel, Pascal and kebab case to snake case, e.g. `requestCount` or
+    /// `RequestCount` to `request_count`, and `HTTPErrors` to `http_errors`
+    Snake,
+}
+
+/// How metrics without a [`MetricDescriptor`] are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingDescriptor {
+    /// Write them as untyped metrics without help
+    #[default]
+    Untyped,
+    /// Don't write them
+    Skip,
+    /// Fail with [`PrometheusError::MissingDescriptor`]
+    Error,
+}
+
+/// How a [`NativeHistogram`](crate::NativeHistogram) is written in text formats, which can't
+/// carry native buckets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NativeFallback {
+    /// One classic bucket per populated native bucket
+    #[default]
+    Classic,
+    /// Only the `+Inf` bucket, with the sum and count
+    Inf,
+}
+
+/// Source of a label attached to a sample, used to resolve duplicate label names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelSource {
+    /// Labels set with [`PrometheusSerializer::set_current_labels`]
+    Current,
+    /// Common labels passed to the serializer
+    Common,
+    /// Labels from the metric's [`MetricDescriptor`]
+    Descriptor,
+}
+
+#[derive(Debug)]
+pub(crate) struct MetricFamily {
+    pub(crate) name: String,
+    pub(crate) metric_type: MetricType,
+    pub(crate) help: String,
+    pub(crate) unit: Option<MetricUnit>,
+    pub(crate) samples: IndexMap<String, Sample>,
 }
 
 #[derive(Debug)]
-struct MetricFamily {
-    header: String,
-    samples: IndexMap<String, String>,
+pub(crate) struct Sample {
+    pub(crate) name: String,
+    pub(crate) labels: Vec<(String, String)>,
+    pub(crate) value: String,
+    #[cfg(feature = "protobuf")]
+    pub(crate) native: Option<Box<NativeBuckets>>,
+}
+
+/// A numeric sample value, kept in its original type so integers are formatted exactly.
+#[derive(Debug, Clone, Copy)]
+enum Number {
+    Int(i64),
+    UInt(u64),
+    F32(f32),
+    F64(f64),
+}
+
+impl Number {
+    #[allow(clippy::cast_precision_loss)]
+    fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(v) => v as f64,
+            Number::UInt(v) => v as f64,
+            Number::F32(v) => f64::from(v),
+            Number::F64(v) => v,
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Number::Int(v) => write!(f, "{v}"),
+            Number::UInt(v) => write!(f, "{v}"),
+            Number::F32(v) if v.is_finite() => write!(f, "{v}"),
+            Number::F64(v) if v.is_finite() => write!(f, "{v}"),
+            _ => {
+                let v = self.as_f64();
+                if v.is_nan() {
+                    f.write_str("NaN")
+                } else if v > 0.0 {
+                    f.write_str("+Inf")
+                } else {
+                    f.write_str("-Inf")
+                }
+            }
+        }
+    }
+}
+
+/// A sample of the metric at the current prefix, before inherited labels are applied.
+struct RawSample {
+    /// Suffix appended to the metric name, e.g. `_bucket`
+    suffix: &'static str,
+    /// Labels in addition to the inherited ones
+    labels: Vec<(String, String)>,
+    /// Formatted value
+    value: String,
+    /// Native histogram buckets, carried by the `_count` sample of a native histogram
+    #[cfg(feature = "protobuf")]
+    native: Option<Box<NativeBuckets>>,
+}
+
+impl RawSample {
+    fn new(labels: Vec<(String, String)>, value: String) -> Self {
+        Self::with_suffix("", labels, value)
+    }
+
+    fn with_suffix(suffix: &'static str, labels: Vec<(String, String)>, value: String) -> Self {
+        RawSample {
+            suffix,
+            labels,
+            value,
+            #[cfg(feature = "protobuf")]
+            native: None,
+        }
+    }
 }
 
 /// A custom serializer that flattens structs into Prometheus metrics.
 pub struct PrometheusSerializer<'s> {
-    /// Current prefix (path) being processed. Nested fields append `_field_name`.
+    /// Current prefix (path) being processed. Nested fields append the separator and their
+    /// field name.
     current_prefix: String,
+    /// Separator between the field names of a path.
+    separator: String,
+    /// Case conversion applied to field names.
+    field_case: FieldCase,
+    /// Whether to collapse repeated underscores in field names.
+    collapse_underscores: bool,
     /// Metric metadata (help, type, labels) keyed by metric name.
     metadata: &'s HashMap<&'s str, MetricDescriptor<'s>>,
     /// Default descriptor for metrics without explicit metadata.
@@ -63,6 +403,47 @@ pub struct PrometheusSerializer<'s> {
     current_labels: Vec<(String, String)>,
     /// Stores metric families keyed by metric name.
     families: IndexMap<String, MetricFamily>,
+    /// Whether to sort families, samples and labels for byte-stable output.
+    sorted: bool,
+    /// Label sources ordered from highest to lowest precedence.
+    label_precedence: [LabelSource; 3],
+    /// Text format written by `finish`.
+    format: TextFormat,
+    /// Whether to enforce counter naming and value semantics.
+    strict_counters: bool,
+    /// How native histograms are written in text formats.
+    native_fallback: NativeFallback,
+    /// Values captured for the info or stateset metric being serialized, if any.
+    collected: Option<Collected>,
+    /// Unit implied by the value being written, e.g. seconds for a `Duration`.
+    value_unit: Option<MetricUnit>,
+    /// Metric type carried by the value being written, e.g. counter for a [`Counter`](crate::Counter).
+    value_type: Option<MetricType>,
+    /// Patterns of the paths to write. When empty, all paths are written.
+    include: Vec<String>,
+    /// Patterns of the paths not to write.
+    exclude: Vec<String>,
+    /// How metrics without a descriptor are handled.
+    missing_descriptor: MissingDescriptor,
+    /// Keys of the descriptors that matched a metric.
+    used_descriptors: HashSet<&'s str>,
+    /// Key of the map entry being serialized, waiting for its value.
+    map_key: Option<String>,
+    /// Original map keys written at each path, to detect keys sanitized to the same segment.
+    map_keys: IndexMap<String, IndexSet<String>>,
+    /// Paths of the metrics without a descriptor.
+    undescribed: IndexSet<String>,
+    /// Paths written under each metric name.
+    metric_paths: IndexMap<String, IndexSet<String>>,
+}
+
+/// Scalar values captured while serializing a value whose fields turn into labels of a
+/// single metric (info and stateset metrics) instead of separate metrics.
+struct Collected {
+    /// Length of the metric's prefix, stripped from the captured field paths.
+    prefix_len: usize,
+    /// Captured field paths and values.
+    values: Vec<(String, String)>,
 }
 
 impl<'s> PrometheusSerializer<'s> {
@@ -78,6 +459,9 @@ impl<'s> PrometheusSerializer<'s> {
     {
         PrometheusSerializer {
             current_prefix: String::new(),
+            separator: "_".to_owned(),
+            field_case: FieldCase::default(),
+            collapse_underscores: false,
             metadata,
             default_desc: MetricDescriptor::default(),
             namespace: namespace.map(Into::into),
@@ -90,6 +474,26 @@ impl<'s> PrometheusSerializer<'s> {
                 .collect(),
             current_labels: Vec::new(),
             families: IndexMap::new(),
+            sorted: false,
+            label_precedence: [
+                LabelSource::Descriptor,
+                LabelSource::Current,
+                LabelSource::Common,
+            ],
+            format: TextFormat::default(),
+            strict_counters: false,
+            native_fallback: NativeFallback::default(),
+            collected: None,
+            value_unit: None,
+            value_type: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            missing_descriptor: MissingDescriptor::default(),
+            used_descriptors: HashSet::new(),
+            map_key: None,
+            map_keys: IndexMap::new(),
+            undescribed: IndexSet::new(),
+            metric_paths: IndexMap::new(),
         }
     }
 
@@ -101,6 +505,186 @@ impl<'s> PrometheusSerializer<'s> {
         self.current_labels = labels.into_iter().collect();
     }
 
+    /// Enable deterministic output. When set, families are sorted by name, samples within a
+    /// family by their label set, and labels within a sample by name, so the output is
+    /// byte-stable regardless of field or map iteration order.
+    pub fn set_sorted(&mut self, sorted: bool) {
+        self.sorted = sorted;
+    }
+
+    /// Set the precedence of label sources, from highest to lowest. When the same label name
+    /// is set by more than one source, only the value from the highest-precedence source is
+    /// kept. Defaults to descriptor > current > common.
+    pub fn set_label_precedence(&mut self, precedence: [LabelSource; 3]) {
+        self.label_precedence = precedence;
+    }
+
+    /// Set the text format written by [`finish`](Self::finish). Defaults to the classic
+    /// Prometheus text format.
+    pub fn set_format(&mut self, format: TextFormat) {
+        self.format = format;
+    }
+
+    /// Enforce counter semantics. When set, the `_total` suffix is appended to counter names
+    /// that lack it, and serializing a negative or NaN counter value fails with
+    /// [`PrometheusError::InvalidCounterValue`].
+    pub fn set_strict_counters(&mut self, strict: bool) {
+        self.strict_counters = strict;
+    }
+
+    /// Set how a [`NativeHistogram`](crate::NativeHistogram) is written in text formats. The
+    /// protobuf format only carries the native buckets. Defaults to classic buckets.
+    pub fn set_native_fallback(&mut self, fallback: NativeFallback) {
+        self.native_fallback = fallback;
+    }
+
+    /// Set the separator between the field names of nested structs. Defaults to `_`.
+    ///
+    /// # Errors
+    /// Returns [`PrometheusError::InvalidSeparator`] if the separator is empty or has
+    /// characters other than ASCII letters, digits, `_` and `:`.
+    pub fn set_separator(&mut self, separator: impl Into<String>) -> Result<(), PrometheusError> {
+        let separator = separator.into();
+        if separator.is_empty()
+            || !separator
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+        {
+            return Err(PrometheusError::InvalidSeparator { separator });
+        }
+        self.separator = separator;
+        Ok(())
+    }
+
+    /// Set the case conversion applied to field names, e.g. to turn the camel case names of
+    /// types renamed with `#[serde(rename_all = "...")]` into snake case. Defaults to keeping
+    /// field names as they are.
+    pub fn set_field_case(&mut self, case: FieldCase) {
+        self.field_case = case;
+    }
+
+    /// Collapse repeated underscores in field names into one, and trim leading and trailing
+    /// underscores, e.g. `_queue__len_` to `queue_len`.
+    pub fn set_collapse_underscores(&mut self, collapse: bool) {
+        self.collapse_underscores = collapse;
+    }
+
+    /// Only write metrics whose path matches one of the patterns. Paths are the flattened
+    /// field names before any rename or namespace, e.g. `inner_count`, after any
+    /// [case conversion](Self::set_field_case), and patterns are exact
+    /// paths or globs where `*` matches any sequence of characters and `?` a single one.
+    pub fn set_include<I, S>(&mut self, patterns: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.include = patterns.into_iter().map(Into::into).collect();
+    }
+
+    /// Don't write metrics whose path matches one of the patterns, which take precedence over
+    /// the [included](Self::set_include) ones.
+    pub fn set_exclude<I, S>(&mut self, patterns: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude = patterns.into_iter().map(Into::into).collect();
+    }
+
+    /// Set how metrics without a [`MetricDescriptor`] are handled. Values carrying their own
+    /// metric type, such as a [`Counter`](crate::Counter), count as described. Defaults to
+    /// writing them as untyped metrics.
+    pub fn set_missing_descriptor(&mut self, missing: MissingDescriptor) {
+        self.missing_descriptor = missing;
+    }
+
+    /// Keys of the metadata whose descriptor didn't match any metric serialized so far, in
+    /// alphabetical order. Useful to catch typos in the metadata.
+    #[must_use]
+    pub fn unused_descriptors(&self) -> Vec<&'s str> {
+        let mut unused: Vec<&'s str> = self
+            .metadata
+            .keys()
+            .copied()
+            .filter(|key| !self.used_descriptors.contains(key))
+            .collect();
+        unused.sort_unstable();
+        unused
+    }
+
+    /// Diagnostics about the metadata and the metrics serialized so far, e.g. to check in CI
+    /// that the metadata is in sync with a sample value of each metrics struct.
+    #[must_use]
+    pub fn lint(&self) -> Vec<Diagnostic> {
+        let unused =
+            self.unused_descriptors()
+                .into_iter()
+                .map(|key| Diagnostic::UnusedDescriptor {
+                    key: key.to_owned(),
+                });
+        let undescribed = self
+            .undescribed
+            .iter()
+            .map(|path| Diagnostic::Undescribed { path: path.clone() });
+        let collisions = self
+            .metric_paths
+            .iter()
+            .filter(|(_, paths)| paths.len() > 1)
+            .map(|(metric, paths)| Diagnostic::NameCollision {
+                metric: metric.clone(),
+                paths: paths.iter().cloned().collect(),
+            });
+        let key_collisions =
+            self.map_keys
+                .iter()
+                .filter(|(_, keys)| keys.len() > 1)
+                .map(|(path, keys)| Diagnostic::KeyCollision {
+                    path: path.clone(),
+                    keys: keys.iter().cloned().collect(),
+                });
+        unused
+            .chain(undescribed)
+            .chain(collisions)
+            .chain(key_collisions)
+            .collect()
+    }
+
+    /// Serialize `value` with `labels` used as the current labels for all its metrics. Can be
+    /// called many times, e.g. once per tenant, to accumulate values: samples of the same
+    /// metric are grouped under a single family, as with [`extend`](Self::extend).
+    ///
+    /// # Errors
+    /// Returns a `PrometheusError` if serialization fails, or
+    /// [`PrometheusError::DuplicateSeries`] if a series was already written by an earlier call.
+    pub fn serialize_with_labels<T, L>(
+        &mut self,
+        value: &T,
+        labels: L,
+    ) -> Result<(), PrometheusError>
+    where
+        T: ?Sized + Serialize,
+        L: IntoIterator<Item = (String, String)>,
+    {
+        let previous = std::mem::replace(&mut self.current_labels, labels.into_iter().collect());
+        let families = std::mem::take(&mut self.families);
+        let result = value.serialize(&mut *self);
+        self.current_labels = previous;
+        let written = std::mem::replace(&mut self.families, families);
+        result?;
+        self.merge_families(written)
+    }
+
+    /// Merge the metrics buffered by another serializer into this one. Samples of families
+    /// with the same name are grouped under the family already present here.
+    ///
+    /// # Errors
+    /// Returns [`PrometheusError::TypeConflict`] if both serializers contain a family with
+    /// the same name but a different type, or [`PrometheusError::DuplicateSeries`] if both
+    /// contain the same series.
+    pub fn extend(&mut self, other: PrometheusSerializer<'_>) -> Result<(), PrometheusError> {
+        self.merge_families(other.into_families())
+    }
+
     /// Finalizes the serializer by concatenating all buffered metric families.
     ///
     /// # Errors
@@ -109,21 +693,109 @@ impl<'s> PrometheusSerializer<'s> {
     where
         W: io::Write,
     {
+        let format = self.format;
+        let openmetrics = format == TextFormat::OpenMetrics;
         let mut seen = false;
-        for (_, family) in self.families {
-            if seen {
+        for family in self.into_families().into_values() {
+            if seen && !openmetrics {
                 output.write_all(b"\n")?;
             }
-            output.write_all(family.header.as_bytes())?;
-            output.write_all(b"\n")?;
-            for (key, value) in family.samples {
-                output.write_all(key.as_bytes())?;
+            let name = if openmetrics {
+                family.openmetrics_name()
+            } else {
+                &family.name
+            };
+            if !family.help.is_empty() {
+                writeln!(
+                    output,
+                    "# HELP {name} {}",
+                    Self::escape_help(&family.help, format)
+                )?;
+            }
+            writeln!(
+                output,
+                "# TYPE {name} {}",
+                family.metric_type.type_name(format)
+            )?;
+            if let Some(unit) = family.unit.filter(|_| openmetrics) {
+                writeln!(output, "# UNIT {name} {unit}")?;
+            }
+            for sample in family.samples.values() {
+                let sample_name = family.sample_name(sample, format);
+                output.write_all(Self::render_series(&sample_name, &sample.labels).as_bytes())?;
                 output.write_all(b" ")?;
-                output.write_all(value.as_bytes())?;
+                output.write_all(sample.value.as_bytes())?;
                 output.write_all(b"\n")?;
             }
             seen = true;
         }
+        if openmetrics {
+            output.write_all(b"# EOF\n")?;
+        }
+        Ok(())
+    }
+
+    /// Takes the buffered metric families, sorted if deterministic output is enabled.
+    pub(crate) fn into_families(mut self) -> IndexMap<String, MetricFamily> {
+        if self.sorted {
+            self.families.sort_unstable_keys();
+            for family in self.families.values_mut() {
+                for sample in family.samples.values_mut() {
+                    sample.labels.sort_by(|a, b| a.0.cmp(&b.0));
+                }
+                // Samples of one series, e.g. the buckets of a histogram, keep their order.
+                let generated = family.metric_type.generated_label();
+                let series = |sample: &Sample| {
+                    sample
+                        .labels
+                        .iter()
+                        .filter(|(k, _)| Some(k.as_str()) != generated)
+                        .cloned()
+                        .collect::<Vec<_>>()
+                };
+                family
+                    .samples
+                    .sort_by(|_, a, _, b| series(a).cmp(&series(b)));
+            }
+        }
+        self.families
+    }
+
+    /// Adds metric families taken from another serializer. Samples of a family that already
+    /// exists are appended to it, keeping its header.
+    ///
+    /// # Errors
+    /// Returns [`PrometheusError::TypeConflict`] if a family exists with a different type, or
+    /// [`PrometheusError::DuplicateSeries`] if a sample's series already exists.
+    pub(crate) fn merge_families(
+        &mut self,
+        families: IndexMap<String, MetricFamily>,
+    ) -> Result<(), PrometheusError> {
+        for (name, family) in families {
+            let Some(existing) = self.families.get_mut(&name) else {
+                self.families.insert(name, family);
+                continue;
+            };
+            if existing.metric_type != family.metric_type {
+                return Err(PrometheusError::TypeConflict {
+                    metric: name,
+                    first: existing.metric_type,
+                    second: family.metric_type,
+                });
+            }
+            if existing.help.is_empty() {
+                existing.help = family.help;
+            }
+            existing.unit = existing.unit.or(family.unit);
+            for (series, sample) in family.samples {
+                if existing.samples.contains_key(&series) {
+                    return Err(PrometheusError::DuplicateSeries {
+                        series: Self::render_series(&sample.name, &sample.labels),
+                    });
+                }
+                existing.samples.insert(series, sample);
+            }
+        }
         Ok(())
     }
 
@@ -142,84 +814,599 @@ impl<'s> PrometheusSerializer<'s> {
         escaped
     }
 
-    fn sample_key(&self, metric_name: &str, desc: &MetricDescriptor<'_>) -> String {
-        let mut sample_line = metric_name.to_string();
-        if !desc.labels.is_empty()
-            || !self.common_labels.is_empty()
-            || !self.current_labels.is_empty()
-        {
-            sample_line.push('{');
-            for (i, (k, v)) in self
-                .current_labels
-                .iter()
-                .map(|(k, v)| (k.as_str(), v.as_str()))
-                .chain(self.common_labels.iter().copied())
-                .chain(desc.labels.iter().copied())
-                .enumerate()
-            {
+    /// Escapes `\\` and newlines in help text, and `"` in the OpenMetrics format.
+    fn escape_help(help: &str, format: TextFormat) -> String {
+        let mut escaped = String::with_capacity(help.len());
+        for c in help.chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                '"' if format == TextFormat::OpenMetrics => escaped.push_str("\\\""),
+                _ => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    /// Renders a series identifier such as `name{a="b",c="d"}`.
+    fn render_series(metric_name: &str, labels: &[(String, String)]) -> String {
+        let mut series = metric_name.to_string();
+        if !labels.is_empty() {
+            series.push('{');
+            for (i, (k, v)) in labels.iter().enumerate() {
                 if i > 0 {
-                    sample_line.push(',');
+                    series.push(',');
+                }
+                series.push_str(k);
+                series.push_str("=\"");
+                series.push_str(&Self::escape_label_value(v));
+                series.push('"');
+            }
+            series.push('}');
+        }
+        series
+    }
+
+    /// Renders the key identifying a sample within its family. Labels are sorted by name, so
+    /// the same series is detected whatever order its labels were written in.
+    fn series_key(sample_name: &str, labels: &[(String, String)]) -> String {
+        let mut labels = labels.to_vec();
+        labels.sort_by(|a, b| a.0.cmp(&b.0));
+        Self::render_series(sample_name, &labels)
+    }
+
+    /// Collects the labels of a sample from the current, common and descriptor labels.
+    /// Duplicate names are resolved by the configured label precedence, and inherited labels
+    /// listed in the descriptor's `drop_labels` are removed.
+    fn sample_labels(&self, desc: &MetricDescriptor<'_>) -> Vec<(String, String)> {
+        let rank = |source: LabelSource| {
+            self.label_precedence
+                .iter()
+                .position(|s| *s == source)
+                .unwrap_or(usize::MAX)
+        };
+        let inherited = self
+            .current_labels
+            .iter()
+            .map(|(k, v)| (LabelSource::Current, k.as_str(), v.as_str()))
+            .chain(
+                self.common_labels
+                    .iter()
+                    .map(|(k, v)| (LabelSource::Common, *k, *v)),
+            )
+            .filter(|(_, k, _)| !desc.drop_labels.contains(k));
+        let own = desc
+            .labels
+            .iter()
+            .map(|(k, v)| (LabelSource::Descriptor, *k, *v));
+
+        let mut labels: Vec<(LabelSource, &str, &str)> = Vec::new();
+        for (source, k, v) in inherited.chain(own) {
+            if let Some(existing) = labels.iter_mut().find(|(_, ek, _)| *ek == k) {
+                if rank(source) < rank(existing.0) {
+                    *existing = (source, k, v);
                 }
-                sample_line.push_str(k);
-                sample_line.push_str("=\"");
-                sample_line.push_str(&Self::escape_label_value(v));
-                sample_line.push('"');
+            } else {
+                labels.push((source, k, v));
+            }
+        }
+        labels
+            .into_iter()
+            .map(|(_, k, v)| (k.to_owned(), v.to_owned()))
+            .collect()
+    }
+
+    /// Checks that no label uses a reserved name or one generated by the metric type.
+    fn validate_labels(
+        metric_name: &str,
+        metric_type: MetricType,
+        labels: &[(String, String)],
+    ) -> Result<(), PrometheusError> {
+        let generated = metric_type.generated_label();
+        for (label, _) in labels {
+            if label.starts_with("__") {
+                return Err(PrometheusError::ReservedLabel {
+                    metric: metric_name.to_owned(),
+                    label: label.clone(),
+                });
+            }
+            if generated == Some(label.as_str()) {
+                return Err(PrometheusError::LabelConflict {
+                    metric: metric_name.to_owned(),
+                    label: label.clone(),
+                    metric_type,
+                });
             }
-            sample_line.push('}');
         }
-        sample_line
+        Ok(())
+    }
+
+    /// Looks up the descriptor for the current prefix, with or without the namespace.
+    fn find_descriptor(&self) -> Option<&'s MetricDescriptor<'s>> {
+        self.find_descriptor_entry().map(|(_, desc)| desc)
+    }
+
+    /// Looks up the descriptor for the current prefix along with its key in the metadata.
+    fn find_descriptor_entry(&self) -> Option<(&'s str, &'s MetricDescriptor<'s>)> {
+        let metadata = self.metadata;
+        let entry = metadata
+            .get_key_value(self.current_prefix.as_str())
+            .or_else(|| {
+                self.namespace.as_ref().and_then(|ns| {
+                    metadata.get_key_value(format!("{ns}_{}", self.current_prefix).as_str())
+                })
+            });
+        entry.map(|(key, desc)| (*key, desc))
     }
 
     /// Writes a metric line for the current prefix with the given numeric value.
-    fn write_metric(&mut self, value: &str) {
-        let metric_name = &self.current_prefix;
-        let ns_metric_name = if let Some(ns) = &self.namespace {
-            format!("{ns}_{metric_name}")
+    fn write_metric(&mut self, value: &str) -> Result<(), PrometheusError> {
+        if let Some(collected) = &mut self.collected {
+            let path = &self.current_prefix[collected.prefix_len..];
+            let path = path.strip_prefix(&self.separator).unwrap_or(path);
+            collected.values.push((path.to_owned(), value.to_owned()));
+            return Ok(());
+        }
+        self.write_samples(vec![RawSample::new(Vec::new(), value.to_owned())])
+    }
+
+    /// Writes a numeric metric for the current prefix, applying the descriptor's transforms and
+    /// checking counter values if enabled.
+    fn write_number(&mut self, mut value: Number) -> Result<(), PrometheusError> {
+        if self.collected.is_none() && !self.should_write()? {
+            return Ok(());
+        }
+        if self.collected.is_none()
+            && let Some(desc) = self.find_descriptor()
+            && !desc.transforms.is_empty()
+        {
+            value = Number::F64(
+                desc.transforms
+                    .iter()
+                    .fold(value.as_f64(), |v, transform| transform.apply(v)),
+            );
+        }
+        if self.strict_counters && self.collected.is_none() {
+            let found = self.find_descriptor();
+            let v = value.as_f64();
+            if self.metric_type(found) == MetricType::Counter && (v.is_nan() || v < 0.0) {
+                return Err(PrometheusError::InvalidCounterValue {
+                    metric: self.metric_name(found),
+                    value: value.to_string(),
+                });
+            }
+        }
+        self.write_metric(&value.to_string())
+    }
+
+    /// Serializes a value whose fields become labels of a single info or stateset metric.
+    fn write_collected<T>(
+        &mut self,
+        metric_type: MetricType,
+        value: &T,
+    ) -> Result<(), PrometheusError>
+    where
+        T: ?Sized + Serialize,
+    {
+        let values = self.capture(value)?;
+        let samples = if metric_type == MetricType::Info {
+            let labels = values.into_iter().filter(|(k, _)| !k.is_empty()).collect();
+            vec![RawSample::new(labels, "1".to_owned())]
         } else {
-            metric_name.clone()
+            // A stateset is either a set of boolean fields, one per state, or a unit enum
+            // variant naming the active state.
+            let state_label = self.metric_name(self.find_descriptor());
+            values
+                .into_iter()
+                .map(|(state, value)| {
+                    if state.is_empty() {
+                        RawSample::new(vec![(state_label.clone(), value)], "1".to_owned())
+                    } else {
+                        RawSample::new(vec![(state_label.clone(), state)], value)
+                    }
+                })
+                .collect()
         };
-        let desc = self.metadata.get(metric_name.as_str()).unwrap_or_else(|| {
-            self.namespace
-                .as_ref()
-                .and_then(|_| self.metadata.get(ns_metric_name.as_str()))
-                .unwrap_or(&self.default_desc)
+        self.write_samples(samples)
+    }
+
+    /// Serializes a value, capturing its scalars keyed by their path relative to the current
+    /// prefix instead of writing them.
+    fn capture<T>(&mut self, value: &T) -> Result<Vec<(String, String)>, PrometheusError>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.collected = Some(Collected {
+            prefix_len: self.current_prefix.len(),
+            values: Vec::new(),
         });
-        let metric_name = if let Some(rename) = desc.rename {
-            if let Some(ns) = &self.namespace {
-                &format!("{ns}_{rename}")
-            } else {
-                rename
+        let result = value.serialize(&mut *self);
+        let values = self
+            .collected
+            .take()
+            .map(|collected| collected.values)
+            .unwrap_or_default();
+        result.map(|()| values)
+    }
+
+    /// Writes the buckets, sum and count serialized by a [`Histogram`](crate::Histogram).
+    fn write_histogram<T>(&mut self, value: &T) -> Result<(), PrometheusError>
+    where
+        T: ?Sized + Serialize,
+    {
+        let values = self.capture(value)?;
+        let (mut bounds, mut counts, mut sum, mut count) = (Vec::new(), Vec::new(), "0", "0");
+        for (path, value) in &values {
+            match path.as_str() {
+                "bounds" => bounds.push(value.as_str()),
+                "counts" => counts.push(value.as_str()),
+                "sum" => sum = value,
+                "count" => count = value,
+                _ => {}
             }
+        }
+        let bucket = |le: &str, value: &str| {
+            RawSample::with_suffix(
+                "_bucket",
+                vec![("le".to_owned(), le.to_owned())],
+                value.to_owned(),
+            )
+        };
+        let mut samples: Vec<RawSample> = bounds
+            .iter()
+            .zip(&counts)
+            .map(|(le, value)| bucket(le, value))
+            .collect();
+        samples.push(bucket("+Inf", count));
+        samples.push(RawSample::with_suffix("_sum", Vec::new(), sum.to_owned()));
+        samples.push(RawSample::with_suffix(
+            "_count",
+            Vec::new(),
+            count.to_owned(),
+        ));
+        self.write_samples(samples)
+    }
+
+    /// Writes a [`NativeHistogram`](crate::NativeHistogram). Text formats get classic buckets
+    /// derived from the native ones unless the [`NativeFallback`] says otherwise, and the native
+    /// buckets are kept for the protobuf format.
+    fn write_native_histogram<T>(&mut self, value: &T) -> Result<(), PrometheusError>
+    where
+        T: ?Sized + Serialize,
+    {
+        let values = self.capture(value)?;
+        let mut native = NativeBuckets {
+            schema: 0,
+            zero_threshold: 0.0,
+            zero_count: 0,
+            positive: Vec::new(),
+            negative: Vec::new(),
+        };
+        let (mut positive_indexes, mut positive_counts) = (Vec::new(), Vec::new());
+        let (mut negative_indexes, mut negative_counts) = (Vec::new(), Vec::new());
+        let (mut sum, mut count) = ("0", "0");
+        for (path, value) in &values {
+            match path.as_str() {
+                "schema" => native.schema = value.parse().unwrap_or_default(),
+                "zero_threshold" => native.zero_threshold = value.parse().unwrap_or_default(),
+                "zero_count" => native.zero_count = value.parse().unwrap_or_default(),
+                "positive_indexes" => positive_indexes.push(value.parse().unwrap_or_default()),
+                "positive_counts" => positive_counts.push(value.parse().unwrap_or_default()),
+                "negative_indexes" => negative_indexes.push(value.parse().unwrap_or_default()),
+                "negative_counts" => negative_counts.push(value.parse().unwrap_or_default()),
+                "sum" => sum = value,
+                "count" => count = value,
+                _ => {}
+            }
+        }
+        native.positive = positive_indexes.into_iter().zip(positive_counts).collect();
+        native.negative = negative_indexes.into_iter().zip(negative_counts).collect();
+
+        let bucket = |le: String, value: String| {
+            RawSample::with_suffix("_bucket", vec![("le".to_owned(), le)], value)
+        };
+        let mut samples: Vec<RawSample> = if self.native_fallback == NativeFallback::Classic {
+            native
+                .classic_buckets()
+                .into_iter()
+                .map(|(le, value)| bucket(Number::F64(le).to_string(), value.to_string()))
+                .collect()
         } else {
-            ns_metric_name.as_str()
+            Vec::new()
         };
+        samples.push(bucket("+Inf".to_owned(), count.to_owned()));
+        samples.push(RawSample::with_suffix("_sum", Vec::new(), sum.to_owned()));
+        #[cfg_attr(not(feature = "protobuf"), allow(unused_mut))]
+        let mut count = RawSample::with_suffix("_count", Vec::new(), count.to_owned());
+        #[cfg(feature = "protobuf")]
+        {
+            count.native = Some(Box::new(native));
+        }
+        samples.push(count);
+        self.write_samples(samples)
+    }
 
-        let sample_key = self.sample_key(metric_name, desc);
+    /// Writes the quantiles, sum and count serialized by a [`Summary`](crate::Summary).
+    fn write_summary<T>(&mut self, value: &T) -> Result<(), PrometheusError>
+    where
+        T: ?Sized + Serialize,
+    {
+        let values = self.capture(value)?;
+        let (mut quantiles, mut estimates, mut sum, mut count) = (Vec::new(), Vec::new(), "0", "0");
+        for (path, value) in &values {
+            match path.as_str() {
+                "quantiles" => quantiles.push(value.as_str()),
+                "values" => estimates.push(value.as_str()),
+                "sum" => sum = value,
+                "count" => count = value,
+                _ => {}
+            }
+        }
+        let mut samples: Vec<RawSample> = quantiles
+            .iter()
+            .zip(&estimates)
+            .map(|(quantile, value)| {
+                RawSample::new(
+                    vec![("quantile".to_owned(), (*quantile).to_owned())],
+                    (*value).to_owned(),
+                )
+            })
+            .collect();
+        samples.push(RawSample::with_suffix("_sum", Vec::new(), sum.to_owned()));
+        samples.push(RawSample::with_suffix(
+            "_count",
+            Vec::new(),
+            count.to_owned(),
+        ));
+        self.write_samples(samples)
+    }
+
+    /// Type of the metric at the current prefix. A type set in the descriptor takes precedence
+    /// over the type carried by the value.
+    fn metric_type(&self, desc: Option<&MetricDescriptor<'_>>) -> MetricType {
+        match desc.map(|desc| desc.metric_type) {
+            Some(metric_type) if metric_type != MetricType::Untyped => metric_type,
+            _ => self.value_type.unwrap_or_default(),
+        }
+    }
+
+    /// Final metric name for the current prefix, including the namespace and any rename.
+    fn metric_name(&self, desc: Option<&MetricDescriptor<'_>>) -> String {
+        let name = desc
+            .and_then(|desc| desc.rename)
+            .unwrap_or(&self.current_prefix);
+        let mut name = if let Some(ns) = &self.namespace {
+            format!("{ns}_{name}")
+        } else {
+            name.to_owned()
+        };
+        // Paths may start with a digit, e.g. from an integer map key.
+        if name.starts_with(|c: char| c.is_ascii_digit()) {
+            name.insert(0, '_');
+        }
+        let metric_type = self.metric_type(desc);
+        if let Some(unit) = desc.and_then(|desc| desc.unit).or(self.value_unit) {
+            let unit_suffix = format!("_{unit}");
+            let total = metric_type == MetricType::Counter && name.ends_with("_total");
+            if total {
+                name.truncate(name.len() - "_total".len());
+            }
+            if !name.ends_with(&unit_suffix) {
+                name.push_str(&unit_suffix);
+            }
+            if total {
+                name.push_str("_total");
+            }
+        }
+        match metric_type {
+            MetricType::Info if !name.ends_with("_info") => name.push_str("_info"),
+            MetricType::Counter if self.strict_counters && !name.ends_with("_total") => {
+                name.push_str("_total");
+            }
+            _ => {}
+        }
+        name
+    }
+
+    /// Whether the metric at the current prefix should be written, i.e. it isn't filtered out
+    /// by the include and exclude patterns or its descriptor, and it is described if required.
+    /// Marks its descriptor as used.
+    ///
+    /// # Errors
+    /// Returns [`PrometheusError::MissingDescriptor`] if the metric has no descriptor while
+    /// they are required.
+    fn should_write(&mut self) -> Result<bool, PrometheusError> {
+        let found = self.find_descriptor_entry();
+        if let Some((key, _)) = found {
+            self.used_descriptors.insert(key);
+        }
+        let path = self.current_prefix.as_str();
+        if found.is_some_and(|(_, desc)| desc.skip)
+            || self.exclude.iter().any(|pattern| glob_match(pattern, path))
+            || (!self.include.is_empty()
+                && !self.include.iter().any(|pattern| glob_match(pattern, path)))
+        {
+            return Ok(false);
+        }
+        if found.is_some() || self.value_type.is_some() {
+            return Ok(true);
+        }
+        self.undescribed.insert(self.current_prefix.clone());
+        match self.missing_descriptor {
+            MissingDescriptor::Untyped => Ok(true),
+            MissingDescriptor::Skip => Ok(false),
+            MissingDescriptor::Error => Err(PrometheusError::MissingDescriptor {
+                metric: self.metric_name(None),
+            }),
+        }
+    }
+
+    /// Writes samples for the metric at the current prefix.
+    fn write_samples(&mut self, samples: Vec<RawSample>) -> Result<(), PrometheusError> {
+        if !self.should_write()? {
+            return Ok(());
+        }
+        let found = self.find_descriptor();
+        if self.current_prefix.is_empty() && found.is_none_or(|desc| desc.rename.is_none()) {
+            return Err(PrometheusError::EmptyPath);
+        }
+        let metric_name = self.metric_name(found);
+        let metric_type = self.metric_type(found);
+        let desc = found.unwrap_or(&self.default_desc);
+        let inherited = self.sample_labels(desc);
+        Self::validate_labels(&metric_name, metric_type, &inherited)?;
+        self.metric_paths
+            .entry(metric_name.clone())
+            .or_default()
+            .insert(self.current_prefix.clone());
 
         let family = self
             .families
-            .entry(metric_name.to_string())
-            .or_insert_with(|| {
-                let mut header = String::new();
-                if !desc.help.is_empty() {
-                    header.push_str("# HELP ");
-                    header.push_str(metric_name);
-                    header.push(' ');
-                    header.push_str(desc.help);
-                    header.push('\n');
+            .entry(metric_name.clone())
+            .or_insert_with(|| MetricFamily {
+                name: metric_name.clone(),
+                metric_type,
+                help: desc.help.to_owned(),
+                unit: desc.unit.or(self.value_unit),
+                samples: IndexMap::new(),
+            });
+
+        for sample in samples {
+            let name = format!("{metric_name}{}", sample.suffix);
+            // Labels of the sample itself may include the label generated for the type.
+            Self::validate_labels(&metric_name, MetricType::Untyped, &sample.labels)?;
+            if let Some((label, _)) = sample
+                .labels
+                .iter()
+                .find(|(k, _)| inherited.iter().any(|(ik, _)| ik == k))
+            {
+                return Err(PrometheusError::LabelConflict {
+                    metric: metric_name,
+                    label: label.clone(),
+                    metric_type,
+                });
+            }
+            let mut labels = inherited.clone();
+            labels.extend(sample.labels);
+            family.samples.insert(
+                Self::series_key(&name, &labels),
+                Sample {
+                    name,
+                    labels,
+                    value: sample.value,
+                    #[cfg(feature = "protobuf")]
+                    native: sample.native,
+                },
+            );
+        }
+        Ok(())
+    }
+}
+
+impl MetricFamily {
+    /// Name of a sample in the given format. In OpenMetrics, counter samples always have the
+    /// `_total` suffix and gauge histograms use the `_gsum` and `_gcount` suffixes, while they
+    /// are plain histograms in the Prometheus format.
+    fn sample_name<'a>(&self, sample: &'a Sample, format: TextFormat) -> Cow<'a, str> {
+        if format == TextFormat::OpenMetrics
+            && self.metric_type == MetricType::Counter
+            && !sample.name.ends_with("_total")
+        {
+            return Cow::Owned(format!("{}_total", sample.name));
+        }
+        if format == TextFormat::OpenMetrics && self.metric_type == MetricType::GaugeHistogram {
+            for (suffix, gauge_suffix) in [("_sum", "_gsum"), ("_count", "_gcount")] {
+                if let Some(base) = sample.name.strip_suffix(suffix) {
+                    return Cow::Owned(format!("{base}{gauge_suffix}"));
                 }
-                header.push_str("# TYPE ");
-                header.push_str(metric_name);
-                header.push(' ');
-                header.push_str(desc.metric_type.as_ref());
-                MetricFamily {
-                    header,
-                    samples: IndexMap::new(),
+            }
+        }
+        Cow::Borrowed(&sample.name)
+    }
+
+    /// Family name in OpenMetrics, which excludes the sample suffix of counters and info metrics.
+    fn openmetrics_name(&self) -> &str {
+        match self.metric_type {
+            MetricType::Counter => self.name.strip_suffix("_total").unwrap_or(&self.name),
+            MetricType::Info => self.name.strip_suffix("_info").unwrap_or(&self.name),
+            _ => &self.name,
+        }
+    }
+}
+
+/// Turns a map key into a path segment, replacing the characters not valid in metric names
+/// with `_`.
+fn sanitize_key(key: &str) -> String {
+    key.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Matches `text` against a glob `pattern`, where `*` matches any sequence of characters and
+/// `?` matches a single character.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*` and the text position it was tried at, for backtracking.
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
                 }
-            });
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
 
-        family.samples.insert(sample_key, value.to_owned());
+/// Converts a camel, Pascal or kebab case name to snake case. A run of capitals is treated as
+/// one word, its last capital starting a new word if followed by a lowercase letter.
+fn to_snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '-' {
+            snake.push('_');
+            continue;
+        }
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_lower) {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
     }
+    snake
+}
+
+/// Collapses runs of underscores into one and trims leading and trailing underscores.
+fn collapse_underscores(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
 }
 
 /// Primary helper to convert a `T: Serialize` into a Prometheus text string.
@@ -270,7 +1457,140 @@ where
     Ok(())
 }
 
-impl Serializer for &mut PrometheusSerializer<'_> {
+/// Serializer for the fields of a struct. Most structs are flattened into one metric per
+/// field, while some well-known types are captured and written as a single value.
+pub struct StructSerializer<'a, 's> {
+    ser: &'a mut PrometheusSerializer<'s>,
+    /// Names of the seconds and nanoseconds fields, in serialization order, while capturing a
+    /// `Duration` or `SystemTime`.
+    time: Option<[&'static str; 2]>,
+    /// Number of time fields captured so far.
+    seen: usize,
+}
+
+impl StructSerializer<'_, '_> {
+    /// Stops capturing a struct that turned out not to be a `Duration` or `SystemTime`, and
+    /// writes the fields captured so far as ordinary metrics.
+    fn replay(&mut self) -> Result<(), PrometheusError> {
+        self.time = None;
+        let values = self
+            .ser
+            .collected
+            .take()
+            .map(|collected| collected.values)
+            .unwrap_or_default();
+        for (path, value) in values {
+            if let Ok(v) = value.parse::<u64>() {
+                self.ser.serialize_struct_field(&path, &v)?;
+            } else if let Ok(v) = value.parse::<i64>() {
+                self.ser.serialize_struct_field(&path, &v)?;
+            } else if let Ok(v) = value.parse::<f64>() {
+                self.ser.serialize_struct_field(&path, &v)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PrometheusSerializer<'_> {
+    /// Name of a field in a path, after case conversion and underscore collapsing.
+    fn field_segment<'f>(&self, field_name: &'f str) -> Cow<'f, str> {
+        let mut segment = Cow::Borrowed(field_name);
+        if self.field_case == FieldCase::Snake {
+            segment = Cow::Owned(to_snake_case(&segment));
+        }
+        if self.collapse_underscores {
+            let collapsed = collapse_underscores(&segment);
+            // Keep names made only of underscores rather than dropping the path segment.
+            if !collapsed.is_empty() {
+                segment = Cow::Owned(collapsed);
+            }
+        }
+        segment
+    }
+
+    /// Serializes a struct field or map entry, appending its name to the current prefix as
+    /// set by the descriptor's [`Flatten`] rule.
+    fn serialize_struct_field<T>(
+        &mut self,
+        field_name: &str,
+        value: &T,
+    ) -> Result<(), PrometheusError>
+    where
+        T: ?Sized + Serialize,
+    {
+        let old_prefix = self.current_prefix.clone();
+        if !self.current_prefix.is_empty() {
+            self.current_prefix.push_str(&self.separator);
+        }
+        let segment = self.field_segment(field_name);
+        self.current_prefix.push_str(&segment);
+        let found = self.find_descriptor_entry();
+        // Skipping a field skips all the metrics nested in it.
+        if let Some((key, desc)) = found
+            && desc.skip
+            && self.collected.is_none()
+        {
+            self.used_descriptors.insert(key);
+            self.current_prefix = old_prefix;
+            return Ok(());
+        }
+        // Captured paths are relative to the metric's prefix, so they are never rewritten.
+        if let Some((key, desc)) = found
+            && desc.flatten != Flatten::Nested
+            && self.collected.is_none()
+        {
+            self.used_descriptors.insert(key);
+            self.current_prefix.truncate(old_prefix.len());
+            match desc.flatten {
+                Flatten::Nested | Flatten::Transparent => {}
+                Flatten::Replace(name) => {
+                    if !self.current_prefix.is_empty() {
+                        self.current_prefix.push_str(&self.separator);
+                    }
+                    self.current_prefix.push_str(name);
+                }
+                Flatten::Root(name) => name.clone_into(&mut self.current_prefix),
+            }
+        }
+        match found.map(|(_, desc)| desc.metric_type) {
+            Some(metric_type @ (MetricType::Info | MetricType::StateSet))
+                if self.collected.is_none() =>
+            {
+                self.write_collected(metric_type, value)?;
+            }
+            _ => value.serialize(&mut *self)?,
+        }
+        self.current_prefix = old_prefix;
+        Ok(())
+    }
+
+    /// Writes the `Duration` or `SystemTime` whose fields were captured as a single value in
+    /// seconds. A `SystemTime` is written as seconds since the UNIX epoch.
+    fn write_time(&mut self) -> Result<(), PrometheusError> {
+        let values = self
+            .collected
+            .take()
+            .map(|collected| collected.values)
+            .unwrap_or_default();
+        let part = |names: [&str; 2]| {
+            values
+                .iter()
+                .find(|(k, _)| names.contains(&k.as_str()))
+                .and_then(|(_, v)| v.parse::<u64>().ok())
+                .unwrap_or(0)
+        };
+        let secs = part(["secs", "secs_since_epoch"]);
+        let nanos = u32::try_from(part(["nanos", "nanos_since_epoch"])).unwrap_or(0);
+        let duration = Duration::new(secs, nanos);
+        self.value_unit = Some(MetricUnit::Seconds);
+        let result = self.write_number(Number::F64(duration.as_secs_f64()));
+        self.value_unit = None;
+        result
+    }
+}
+
+impl<'a, 's> Serializer for &'a mut PrometheusSerializer<'s> {
     type Ok = ();
     type Error = PrometheusError;
 
@@ -282,59 +1602,48 @@ impl Serializer for &mut PrometheusSerializer<'_> {
     type SerializeTupleStruct = Self;
     type SerializeTupleVariant = Self;
     type SerializeMap = Self;
-    type SerializeStruct = Self;
+    type SerializeStruct = StructSerializer<'a, 's>;
     type SerializeStructVariant = Self;
 
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
         if v {
-            self.write_metric("1");
+            self.write_metric("1")
         } else {
-            self.write_metric("0");
+            self.write_metric("0")
         }
-        Ok(())
     }
 
     fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
-        self.write_metric(&v.to_string());
-        Ok(())
+        self.write_number(Number::Int(v.into()))
     }
     fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
-        self.write_metric(&v.to_string());
-        Ok(())
+        self.write_number(Number::Int(v.into()))
     }
     fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
-        self.write_metric(&v.to_string());
-        Ok(())
+        self.write_number(Number::Int(v.into()))
     }
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
-        self.write_metric(&v.to_string());
-        Ok(())
+        self.write_number(Number::Int(v))
     }
 
     fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
-        self.write_metric(&v.to_string());
-        Ok(())
+        self.write_number(Number::UInt(v.into()))
     }
     fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
-        self.write_metric(&v.to_string());
-        Ok(())
+        self.write_number(Number::UInt(v.into()))
     }
     fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
-        self.write_metric(&v.to_string());
-        Ok(())
+        self.write_number(Number::UInt(v.into()))
     }
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
-        self.write_metric(&v.to_string());
-        Ok(())
+        self.write_number(Number::UInt(v))
     }
 
     fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
-        self.write_metric(&v.to_string());
-        Ok(())
+        self.write_number(Number::F32(v))
     }
     fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
-        self.write_metric(&v.to_string());
-        Ok(())
+        self.write_number(Number::F64(v))
     }
 
     fn serialize_char(self, _v: char) -> Result<Self::Ok, Self::Error> {
@@ -342,8 +1651,11 @@ impl Serializer for &mut PrometheusSerializer<'_> {
         Ok(())
     }
 
-    fn serialize_str(self, _v: &str) -> Result<Self::Ok, Self::Error> {
-        // We don't export strings as metrics. Skip.
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        // We don't export strings as metrics, but they can be labels of an info metric.
+        if self.collected.is_some() {
+            return self.write_metric(v);
+        }
         Ok(())
     }
 
@@ -374,18 +1686,39 @@ impl Serializer for &mut PrometheusSerializer<'_> {
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
     ) -> Result<Self::Ok, Self::Error> {
-        // We can skip this, or treat the variant as a label
+        // Only meaningful as the active state of a stateset or a label of an info metric.
+        if self.collected.is_some() {
+            return self.write_metric(variant);
+        }
         Ok(())
     }
 
     fn serialize_newtype_struct<T: ?Sized + Serialize>(
         self,
-        _name: &'static str,
+        name: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error> {
-        value.serialize(self)
+        let value_type = match name {
+            COUNTER_NAME => MetricType::Counter,
+            GAUGE_NAME => MetricType::Gauge,
+            HISTOGRAM_NAME | NATIVE_HISTOGRAM_NAME => MetricType::Histogram,
+            SUMMARY_NAME => MetricType::Summary,
+            _ => return value.serialize(self),
+        };
+        let previous = self.value_type.replace(value_type);
+        let result = match value_type {
+            _ if self.collected.is_some() => value.serialize(&mut *self),
+            MetricType::Histogram if name == NATIVE_HISTOGRAM_NAME => {
+                self.write_native_histogram(value)
+            }
+            MetricType::Histogram => self.write_histogram(value),
+            MetricType::Summary => self.write_summary(value),
+            _ => value.serialize(&mut *self),
+        };
+        self.value_type = previous;
+        result
     }
 
     fn serialize_newtype_variant<T: ?Sized + Serialize>(
@@ -433,10 +1766,29 @@ impl Serializer for &mut PrometheusSerializer<'_> {
 
     fn serialize_struct(
         self,
-        _name: &'static str,
-        _len: usize,
+        name: &'static str,
+        len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        Ok(self)
+        // `Duration` and `SystemTime` serialize as structs of seconds and nanoseconds. Their
+        // fields are checked as they are serialized, since other types may share the name, and
+        // replayed as ordinary fields if they don't match.
+        let fields = match name {
+            "Duration" => Some(["secs", "nanos"]),
+            "SystemTime" => Some(["secs_since_epoch", "nanos_since_epoch"]),
+            _ => None,
+        };
+        let time = fields.filter(|_| len == 2 && self.collected.is_none());
+        if time.is_some() {
+            self.collected = Some(Collected {
+                prefix_len: self.current_prefix.len(),
+                values: Vec::new(),
+            });
+        }
+        Ok(StructSerializer {
+            ser: self,
+            time,
+            seen: 0,
+        })
     }
 
     fn serialize_struct_variant(
@@ -507,11 +1859,25 @@ impl SerializeMap for &mut PrometheusSerializer<'_> {
     type Ok = ();
     type Error = PrometheusError;
 
-    fn serialize_key<T: ?Sized + Serialize>(&mut self, _key: &T) -> Result<(), Self::Error> {
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Self::Error> {
+        self.map_key = key.serialize(KeySerializer)?;
         Ok(())
     }
-    fn serialize_value<T: ?Sized + Serialize>(&mut self, _value: &T) -> Result<(), Self::Error> {
-        Ok(())
+
+    /// Serializes the value like a struct field named after the sanitized key, skipping
+    /// entries whose key can't be a path segment, e.g. a float.
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let Some(key) = self.map_key.take() else {
+            return Ok(());
+        };
+        let segment = sanitize_key(&key);
+        let mut path = self.current_prefix.clone();
+        if !path.is_empty() {
+            path.push_str(&self.separator);
+        }
+        path.push_str(&self.field_segment(&segment));
+        self.map_keys.entry(path).or_default().insert(key);
+        self.serialize_struct_field(&segment, value)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
@@ -519,7 +1885,7 @@ impl SerializeMap for &mut PrometheusSerializer<'_> {
     }
 }
 
-impl SerializeStruct for &mut PrometheusSerializer<'_> {
+impl SerializeStruct for StructSerializer<'_, '_> {
     type Ok = ();
     type Error = PrometheusError;
 
@@ -528,18 +1894,22 @@ impl SerializeStruct for &mut PrometheusSerializer<'_> {
         field_name: &'static str,
         value: &T,
     ) -> Result<(), PrometheusError> {
-        let old_prefix = self.current_prefix.clone();
-        if !self.current_prefix.is_empty() {
-            self.current_prefix.push('_');
+        if let Some(fields) = self.time {
+            if fields.get(self.seen) == Some(&field_name) {
+                self.seen += 1;
+            } else {
+                self.replay()?;
+            }
         }
-        self.current_prefix.push_str(field_name);
-        value.serialize(&mut **self)?;
-        self.current_prefix = old_prefix;
-        Ok(())
+        self.ser.serialize_struct_field(field_name, value)
     }
 
-    fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(())
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        match self.time {
+            Some(_) if self.seen == 2 => self.ser.write_time(),
+            Some(_) => self.replay(),
+            None => Ok(()),
+        }
     }
 }
 
@@ -559,3 +1929,280 @@ impl SerializeStructVariant for &mut PrometheusSerializer<'_> {
         Ok(())
     }
 }
+
+/// Serializer turning a map key into a string, or `None` if the key is a float, bytes, a unit
+/// or a compound value. Strings, integers, booleans, chars and unit variants are kept.
+struct KeySerializer;
+
+impl Serializer for KeySerializer {
+    type Ok = Option<String>;
+    type Error = PrometheusError;
+    type SerializeSeq = SkippedKey;
+    type SerializeTuple = SkippedKey;
+    type SerializeTupleStruct = SkippedKey;
+    type SerializeTupleVariant = SkippedKey;
+    type SerializeMap = SkippedKey;
+    type SerializeStruct = SkippedKey;
+    type SerializeStructVariant = SkippedKey;
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(v.to_owned()))
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(SkippedKey)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Ok(SkippedKey)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Ok(SkippedKey)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Ok(SkippedKey)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(SkippedKey)
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(SkippedKey)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Ok(SkippedKey)
+    }
+}
+
+/// Compound map key, which can't be a path segment. Its contents are ignored.
+struct SkippedKey;
+
+impl SerializeSeq for SkippedKey {
+    type Ok = Option<String>;
+    type Error = PrometheusError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, _value: &T) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+}
+
+impl SerializeTuple for SkippedKey {
+    type Ok = Option<String>;
+    type Error = PrometheusError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, _value: &T) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+}
+
+impl SerializeTupleStruct for SkippedKey {
+    type Ok = Option<String>;
+    type Error = PrometheusError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, _value: &T) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+}
+
+impl SerializeTupleVariant for SkippedKey {
+    type Ok = Option<String>;
+    type Error = PrometheusError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, _value: &T) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+}
+
+impl SerializeMap for SkippedKey {
+    type Ok = Option<String>;
+    type Error = PrometheusError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, _key: &T) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, _value: &T) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+}
+
+impl SerializeStruct for SkippedKey {
+    type Ok = Option<String>;
+    type Error = PrometheusError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        _value: &T,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+}
+
+impl SerializeStructVariant for SkippedKey {
+    type Ok = Option<String>;
+    type Error = PrometheusError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        _value: &T,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+}
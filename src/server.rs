@@ -0,0 +1,221 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::{MetricDescriptor, PROMETHEUS_CONTENT_TYPE, write_prometheus_text};
+
+/// Path served by [`MetricsServer`].
+const METRICS_PATH: &str = "/metrics";
+/// How long a client may take to send its request and read the response.
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+/// Maximum size of the request line and headers; anything beyond is not read.
+const MAX_REQUEST_HEAD: u64 = 8 * 1024;
+
+/// A minimal blocking HTTP server exposing `/metrics` in the Prometheus text format, built on
+/// `std::net` only.
+///
+/// Requests are handled one at a time on a background thread. Each request calls the snapshot
+/// closure and serializes the returned value. The server stops when [`shutdown`](Self::shutdown)
+/// is called or when it is dropped.
+pub struct MetricsServer {
+    local_addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    /// Bind to `addr` and start serving the values returned by `snapshot`.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if binding the address or spawning the server thread fails.
+    pub fn bind<A, F, T, L, Li>(
+        addr: A,
+        namespace: Option<impl Into<String>>,
+        metadata: HashMap<&'static str, MetricDescriptor<'static>>,
+        common_labels: L,
+        snapshot: F,
+    ) -> io::Result<Self>
+    where
+        A: ToSocketAddrs,
+        F: Fn() -> T + Send + 'static,
+        T: Serialize,
+        L: IntoIterator<Item = Li>,
+        Li: Borrow<(&'static str, &'static str)>,
+    {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let namespace: Option<String> = namespace.map(Into::into);
+        let common_labels: Vec<(&'static str, &'static str)> =
+            common_labels.into_iter().map(|el| *el.borrow()).collect();
+
+        let thread = thread::Builder::new()
+            .name("serde_prom-metrics".to_owned())
+            .spawn({
+                let stop = Arc::clone(&stop);
+                move || {
+                    for stream in listener.incoming() {
+                        if stop.load(Ordering::SeqCst) {
+                            break;
+                        }
+                        let Ok(stream) = stream else {
+                            continue;
+                        };
+                        // A failing client must not take down the server.
+                        let _ = handle_connection(&stream, || {
+                            let mut body = Vec::new();
+                            write_prometheus_text(
+                                &snapshot(),
+                                &mut body,
+                                namespace.as_deref(),
+                                &metadata,
+                                &common_labels,
+                            )
+                            .map(|()| body)
+                        });
+                    }
+                }
+            })?;
+
+        Ok(MetricsServer {
+            local_addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// The address the server is listening on.
+    #[must_use]
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stop the server and wait for the request being served, if any, to complete.
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        self.stop.store(true, Ordering::SeqCst);
+        // Wake up the blocking `accept` so the server thread sees the stop flag.
+        let mut wake_addr = self.local_addr;
+        if wake_addr.ip().is_unspecified() {
+            wake_addr.set_ip(if wake_addr.is_ipv4() {
+                Ipv4Addr::LOCALHOST.into()
+            } else {
+                Ipv6Addr::LOCALHOST.into()
+            });
+        }
+        let _ = TcpStream::connect_timeout(&wake_addr, IO_TIMEOUT);
+        let _ = thread.join();
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Reads a single request from the stream and writes the response.
+fn handle_connection<R>(stream: &TcpStream, render: R) -> io::Result<()>
+where
+    R: FnOnce() -> Result<Vec<u8>, crate::PrometheusError>,
+{
+    // A client that stalls or trickles data would otherwise block the only serving thread, and
+    // shutdown with it.
+    let mut stream = DeadlineStream {
+        stream,
+        deadline: Instant::now() + IO_TIMEOUT,
+    };
+    let mut lines = BufReader::new((&mut stream).take(MAX_REQUEST_HEAD));
+    let mut request_line = String::new();
+    lines.read_line(&mut request_line)?;
+    // Drain the headers; their content doesn't matter to us.
+    let mut line = String::new();
+    while lines.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET" | "HEAD", METRICS_PATH) => match render() {
+            Ok(body) => ("200 OK", PROMETHEUS_CONTENT_TYPE, body),
+            Err(e) => (
+                "500 Internal Server Error",
+                "text/plain; charset=utf-8",
+                e.to_string().into_bytes(),
+            ),
+        },
+        (_, METRICS_PATH) => (
+            "405 Method Not Allowed",
+            "text/plain; charset=utf-8",
+            b"method not allowed\n".to_vec(),
+        ),
+        _ => (
+            "404 Not Found",
+            "text/plain; charset=utf-8",
+            b"not found\n".to_vec(),
+        ),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    if method != "HEAD" {
+        stream.write_all(&body)?;
+    }
+    stream.flush()
+}
+
+/// A stream whose reads and writes fail once the deadline has passed, however the client
+/// spreads out its data.
+struct DeadlineStream<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl DeadlineStream<'_> {
+    /// Time left before the deadline, failing with `TimedOut` once it has passed.
+    fn remaining(&self) -> io::Result<Duration> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        Ok(remaining)
+    }
+}
+
+impl Read for DeadlineStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.set_read_timeout(Some(self.remaining()?))?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for DeadlineStream<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.set_write_timeout(Some(self.remaining()?))?;
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
@@ -0,0 +1,320 @@
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use serde::{Serialize, Serializer};
+
+/// Newtype struct name marking a serialized [`Summary`].
+pub(crate) const SUMMARY_NAME: &str = "$serde_prom::Summary";
+
+/// Default quantiles and their allowed rank errors.
+pub const DEFAULT_OBJECTIVES: [(f64, f64); 3] = [(0.5, 0.05), (0.9, 0.01), (0.99, 0.001)];
+/// Default duration over which quantiles are computed.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_mins(10);
+/// Default number of buckets the window is divided into.
+pub const DEFAULT_AGE_BUCKETS: usize = 5;
+
+/// Number of observations buffered before they are merged into a stream.
+const BUFFER_SIZE: usize = 500;
+
+/// A summary recorder estimating quantiles over a sliding time window.
+///
+/// Quantiles are estimated with the CKMS algorithm for targeted quantiles, each within its
+/// allowed rank error, over the observations of the last `max_age`. The window slides in
+/// `age_buckets` steps. The sum and count cover all observations.
+///
+/// It serializes as a summary metric, expanded to `quantile`-labelled, `_sum` and `_count`
+/// samples, without needing a [`MetricDescriptor`](crate::MetricDescriptor). Quantiles of an
+/// empty window are NaN. With other serializers, it serializes as its quantiles, their values,
+/// sum and count.
+#[derive(Debug)]
+pub struct Summary {
+    /// Targeted quantiles and their allowed errors, sorted by quantile.
+    objectives: Vec<(f64, f64)>,
+    /// Duration of each age bucket.
+    bucket_duration: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// One stream per age bucket, each started at a different time.
+    streams: Vec<Stream>,
+    /// Index of the oldest stream, which covers the whole window.
+    head: usize,
+    /// When the oldest stream is reset and the next one becomes the head.
+    head_expires: Instant,
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Serialize)]
+struct SummarySnapshot {
+    quantiles: Vec<f64>,
+    values: Vec<f64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Summary {
+    /// Create a summary with the given quantiles and allowed rank errors, e.g. `(0.99, 0.001)`,
+    /// computed over the [`DEFAULT_MAX_AGE`]. Quantiles must be strictly between 0 and 1, and
+    /// errors between 0 and 1, or they are ignored.
+    pub fn new(objectives: impl IntoIterator<Item = (f64, f64)>) -> Self {
+        Self::with_window(objectives, DEFAULT_MAX_AGE, DEFAULT_AGE_BUCKETS)
+    }
+
+    /// Create a summary computing quantiles over the last `max_age`, divided into
+    /// `age_buckets` steps (at least one).
+    pub fn with_window(
+        objectives: impl IntoIterator<Item = (f64, f64)>,
+        max_age: Duration,
+        age_buckets: usize,
+    ) -> Self {
+        let mut objectives: Vec<(f64, f64)> = objectives
+            .into_iter()
+            .filter(|&(q, e)| q > 0.0 && q < 1.0 && (0.0..1.0).contains(&e))
+            .collect();
+        objectives.sort_by(|a, b| a.0.total_cmp(&b.0));
+        objectives.dedup_by(|a, b| a.0.total_cmp(&b.0).is_eq());
+        let age_buckets = age_buckets.max(1);
+        let bucket_duration = max_age / u32::try_from(age_buckets).unwrap_or(u32::MAX);
+        Summary {
+            objectives,
+            bucket_duration,
+            state: Mutex::new(State {
+                streams: (0..age_buckets).map(|_| Stream::default()).collect(),
+                head: 0,
+                head_expires: Instant::now() + bucket_duration,
+                sum: 0.0,
+                count: 0,
+            }),
+        }
+    }
+
+    /// Record an observation.
+    pub fn observe(&self, value: f64) {
+        self.observe_at(value, Instant::now());
+    }
+
+    /// Records an observation made at `now`.
+    pub(crate) fn observe_at(&self, value: f64, now: Instant) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        self.rotate(&mut state, now);
+        for stream in &mut state.streams {
+            stream.buffer.push(value);
+            if stream.buffer.len() >= BUFFER_SIZE {
+                stream.flush(&self.objectives);
+            }
+        }
+        state.sum += value;
+        state.count += 1;
+    }
+
+    /// Estimated values of the quantiles over the current window, in the order of the
+    /// quantiles. Values are NaN if there were no observations in the window.
+    #[must_use]
+    pub fn quantiles(&self) -> Vec<(f64, f64)> {
+        self.quantiles_at(Instant::now())
+    }
+
+    /// Estimated values of the quantiles over the window ending at `now`.
+    pub(crate) fn quantiles_at(&self, now: Instant) -> Vec<(f64, f64)> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        self.estimate(&mut state, now)
+    }
+
+    /// Number of observations.
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .count
+    }
+
+    /// Sum of all observations.
+    #[must_use]
+    pub fn sum(&self) -> f64 {
+        self.state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .sum
+    }
+
+    /// Estimates the quantiles from the oldest stream, which covers the whole window ending
+    /// at `now`.
+    fn estimate(&self, state: &mut State, now: Instant) -> Vec<(f64, f64)> {
+        self.rotate(state, now);
+        let head = state.head;
+        let stream = &mut state.streams[head];
+        stream.flush(&self.objectives);
+        self.objectives
+            .iter()
+            .map(|&(q, _)| (q, stream.query(q, &self.objectives)))
+            .collect()
+    }
+
+    /// Resets the streams whose age bucket has expired.
+    fn rotate(&self, state: &mut State, now: Instant) {
+        let window = self.bucket_duration * u32::try_from(state.streams.len()).unwrap_or(1);
+        if now.saturating_duration_since(state.head_expires) >= window {
+            // Idle for longer than the window: all streams have expired.
+            state.streams.iter_mut().for_each(Stream::reset);
+            state.head_expires = now + self.bucket_duration;
+            return;
+        }
+        while now >= state.head_expires {
+            let head = state.head;
+            state.streams[head].reset();
+            state.head = (head + 1) % state.streams.len();
+            state.head_expires += self.bucket_duration;
+        }
+    }
+}
+
+impl Default for Summary {
+    /// A summary with the [`DEFAULT_OBJECTIVES`].
+    fn default() -> Self {
+        Self::new(DEFAULT_OBJECTIVES)
+    }
+}
+
+impl Serialize for Summary {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let snapshot = {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            let (quantiles, values) = self
+                .estimate(&mut state, Instant::now())
+                .into_iter()
+                .unzip();
+            SummarySnapshot {
+                quantiles,
+                values,
+                sum: state.sum,
+                count: state.count,
+            }
+        };
+        serializer.serialize_newtype_struct(SUMMARY_NAME, &snapshot)
+    }
+}
+
+/// A sample of a CKMS stream, standing for `width` observations ranked right after the
+/// previous sample, with an uncertainty of `delta` on its rank.
+#[derive(Debug, Clone, Copy)]
+struct Item {
+    value: f64,
+    width: f64,
+    delta: f64,
+}
+
+/// A CKMS stream estimating targeted quantiles.
+#[derive(Debug, Default)]
+struct Stream {
+    items: Vec<Item>,
+    /// Number of observations merged into `items`.
+    n: f64,
+    /// Observations not merged yet.
+    buffer: Vec<f64>,
+}
+
+impl Stream {
+    fn reset(&mut self) {
+        self.items.clear();
+        self.n = 0.0;
+        self.buffer.clear();
+    }
+
+    /// Maximum rank uncertainty allowed at rank `r` to meet the error of every objective.
+    fn allowed_error(&self, r: f64, objectives: &[(f64, f64)]) -> f64 {
+        objectives
+            .iter()
+            .map(|&(q, e)| {
+                if q * self.n <= r {
+                    2.0 * e * r / q
+                } else {
+                    2.0 * e * (self.n - r) / (1.0 - q)
+                }
+            })
+            .fold(f64::MAX, f64::min)
+    }
+
+    /// Merges the buffered observations into the stream and compresses it.
+    fn flush(&mut self, objectives: &[(f64, f64)]) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.sort_by(f64::total_cmp);
+        let mut r = 0.0;
+        let mut i = 0;
+        for &value in &buffer {
+            while i < self.items.len() && self.items[i].value <= value {
+                r += self.items[i].width;
+                i += 1;
+            }
+            let delta = if i == self.items.len() {
+                0.0
+            } else {
+                (self.allowed_error(r, objectives).floor() - 1.0).max(0.0)
+            };
+            self.items.insert(
+                i,
+                Item {
+                    value,
+                    width: 1.0,
+                    delta,
+                },
+            );
+            i += 1;
+            self.n += 1.0;
+            r += 1.0;
+        }
+        buffer.clear();
+        self.buffer = buffer;
+        self.compress(objectives);
+    }
+
+    /// Merges adjacent items whose combined uncertainty stays within the allowed error.
+    fn compress(&mut self, objectives: &[(f64, f64)]) {
+        let Some(mut x) = self.items.last().copied() else {
+            return;
+        };
+        let mut xi = self.items.len() - 1;
+        let mut r = self.n - 1.0 - x.width;
+        for i in (0..self.items.len() - 1).rev() {
+            let c = self.items[i];
+            if c.width + x.width + x.delta <= self.allowed_error(r, objectives) {
+                x.width += c.width;
+                self.items[xi] = x;
+                self.items.remove(i);
+                xi -= 1;
+            } else {
+                x = c;
+                xi = i;
+            }
+            r -= c.width;
+        }
+    }
+
+    /// Estimated value of quantile `q`, or NaN if the stream is empty.
+    fn query(&self, q: f64, objectives: &[(f64, f64)]) -> f64 {
+        let Some(first) = self.items.first() else {
+            return f64::NAN;
+        };
+        let mut t = (q * self.n).ceil();
+        t += (self.allowed_error(t, objectives) / 2.0).ceil();
+        let mut prev = first;
+        let mut r = 0.0;
+        for item in &self.items[1..] {
+            r += prev.width;
+            if r + item.width + item.delta > t {
+                return prev.value;
+            }
+            prev = item;
+        }
+        prev.value
+    }
+}
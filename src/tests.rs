@@ -1,12 +1,14 @@
 use indoc::indoc;
 use pretty_assertions::assert_eq;
 use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
 
-use openmetrics_parser::prometheus::parse_prometheus;
+use openmetrics_parser::{openmetrics::parse_openmetrics, prometheus::parse_prometheus};
 use serde::Serialize;
 
 use crate::{
-    ser::{MetricDescriptor, MetricType},
+    LabelSource, NativeFallback, PrometheusError, PrometheusSerializer, TextFormat, Transform,
+    ser::{MetricDescriptor, MetricType, MetricUnit},
     to_prometheus_text,
 };
 
@@ -48,6 +50,7 @@ fn serialize_nested() {
             help: "Total number of requests processed",
             labels: vec![],
             rename: None,
+            ..Default::default()
         },
     );
     meta.insert(
@@ -57,6 +60,7 @@ fn serialize_nested() {
             help: "Total number of errors",
             labels: vec![("endpoint", "login")],
             rename: None,
+            ..Default::default()
         },
     );
     meta.insert(
@@ -66,6 +70,7 @@ fn serialize_nested() {
             help: "Current value from inner struct",
             labels: vec![],
             rename: None,
+            ..Default::default()
         },
     );
     meta.insert(
@@ -75,6 +80,7 @@ fn serialize_nested() {
             help: "Threshold value from inner struct",
             labels: vec![],
             rename: None,
+            ..Default::default()
         },
     );
 
@@ -128,6 +134,7 @@ fn test_parse_simple() {
             help: "First one",
             labels: vec![],
             rename: Some("one_total"),
+            ..Default::default()
         },
     );
     meta.insert(
@@ -137,6 +144,7 @@ fn test_parse_simple() {
             help: "Second one",
             labels: vec![("thing", "stuff")],
             rename: Some("two_total"),
+            ..Default::default()
         },
     );
     meta.insert(
@@ -146,6 +154,7 @@ fn test_parse_simple() {
             help: "Third one",
             labels: vec![],
             rename: Some("three_total"),
+            ..Default::default()
         },
     );
     meta.insert(
@@ -155,6 +164,7 @@ fn test_parse_simple() {
             help: "Sub A",
             labels: vec![],
             rename: None,
+            ..Default::default()
         },
     );
     meta.insert(
@@ -164,6 +174,7 @@ fn test_parse_simple() {
             help: "Sub B",
             labels: vec![],
             rename: None,
+            ..Default::default()
         },
     );
 
@@ -193,3 +204,2378 @@ fn test_parse_simple() {
     println!("output:\n{output}");
     let _parsed = parse_prometheus(&output).unwrap();
 }
+
+#[test]
+fn serialize_sorted() {
+    #[derive(Serialize)]
+    struct Data {
+        zeta: u32,
+        alpha: u32,
+    }
+
+    let mut meta = HashMap::new();
+    meta.insert(
+        "alpha",
+        MetricDescriptor {
+            metric_type: MetricType::Gauge,
+            help: "Alpha",
+            labels: vec![("kind", "first")],
+            rename: None,
+            ..Default::default()
+        },
+    );
+
+    let common_labels = vec![("zone", "eu"), ("app", "myapp")];
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &common_labels);
+    serializer.set_sorted(true);
+    serializer.set_current_labels(vec![("host".to_string(), "b".to_string())]);
+    Data { zeta: 1, alpha: 2 }
+        .serialize(&mut serializer)
+        .unwrap();
+    serializer.set_current_labels(vec![("host".to_string(), "a".to_string())]);
+    Data { zeta: 3, alpha: 4 }
+        .serialize(&mut serializer)
+        .unwrap();
+
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    let output = String::from_utf8(buf).unwrap();
+
+    let expected = indoc! {"
+        # HELP alpha Alpha
+        # TYPE alpha gauge
+        alpha{app=\"myapp\",host=\"a\",kind=\"first\",zone=\"eu\"} 4
+        alpha{app=\"myapp\",host=\"b\",kind=\"first\",zone=\"eu\"} 2
+
+        # TYPE zeta untyped
+        zeta{app=\"myapp\",host=\"a\",zone=\"eu\"} 3
+        zeta{app=\"myapp\",host=\"b\",zone=\"eu\"} 1
+    "};
+    assert_eq!(output, expected);
+
+    // Samples merged from an unsorted serializer have their labels sorted too.
+    let no_meta = HashMap::new();
+    let mut merged = PrometheusSerializer::new(None::<&str>, &no_meta, [("zz", "1")]);
+    merged.set_sorted(true);
+    let mut other = PrometheusSerializer::new(None::<&str>, &no_meta, [("zz", "2"), ("aa", "2")]);
+    Data { zeta: 5, alpha: 6 }.serialize(&mut other).unwrap();
+    merged.extend(other).unwrap();
+    let mut buf = Vec::new();
+    merged.finish(&mut buf).unwrap();
+    let output = String::from_utf8(buf).unwrap();
+    let expected = indoc! {"
+        # TYPE alpha untyped
+        alpha{aa=\"2\",zz=\"2\"} 6
+
+        # TYPE zeta untyped
+        zeta{aa=\"2\",zz=\"2\"} 5
+    "};
+    assert_eq!(output, expected);
+
+    // The same series written with its labels in another order is a duplicate.
+    let mut merged = PrometheusSerializer::new(None::<&str>, &no_meta, [("a", "1"), ("b", "2")]);
+    Data { zeta: 1, alpha: 2 }.serialize(&mut merged).unwrap();
+    let mut other = PrometheusSerializer::new(None::<&str>, &no_meta, [("b", "2"), ("a", "1")]);
+    Data { zeta: 1, alpha: 2 }.serialize(&mut other).unwrap();
+    assert!(matches!(
+        merged.extend(other),
+        Err(PrometheusError::DuplicateSeries { .. })
+    ));
+}
+
+#[test]
+fn label_precedence() {
+    #[derive(Serialize)]
+    struct Data {
+        requests: u32,
+        errors: u32,
+    }
+
+    let mut meta = HashMap::new();
+    meta.insert(
+        "requests",
+        MetricDescriptor {
+            labels: vec![("app", "frontend")],
+            drop_labels: vec!["zone"],
+            ..Default::default()
+        },
+    );
+    meta.insert(
+        "errors",
+        MetricDescriptor {
+            labels: vec![("host", "h2")],
+            ..Default::default()
+        },
+    );
+
+    let common_labels = vec![("app", "myapp"), ("zone", "eu")];
+    let data = Data {
+        requests: 10,
+        errors: 2,
+    };
+
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &common_labels);
+    serializer.set_current_labels(vec![("host".to_string(), "h1".to_string())]);
+    data.serialize(&mut serializer).unwrap();
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    let expected = indoc! {"
+        # TYPE requests untyped
+        requests{host=\"h1\",app=\"frontend\"} 10
+
+        # TYPE errors untyped
+        errors{host=\"h2\",app=\"myapp\",zone=\"eu\"} 2
+    "};
+    assert_eq!(String::from_utf8(buf).unwrap(), expected);
+
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &common_labels);
+    serializer.set_label_precedence([
+        LabelSource::Current,
+        LabelSource::Common,
+        LabelSource::Descriptor,
+    ]);
+    serializer.set_current_labels(vec![("host".to_string(), "h1".to_string())]);
+    data.serialize(&mut serializer).unwrap();
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    let expected = indoc! {"
+        # TYPE requests untyped
+        requests{host=\"h1\",app=\"myapp\"} 10
+
+        # TYPE errors untyped
+        errors{host=\"h1\",app=\"myapp\",zone=\"eu\"} 2
+    "};
+    assert_eq!(String::from_utf8(buf).unwrap(), expected);
+}
+
+#[test]
+fn reserved_labels() {
+    #[derive(Serialize)]
+    struct Data {
+        latency: f64,
+    }
+
+    let data = Data { latency: 0.5 };
+
+    let meta = HashMap::new();
+    let labels = vec![("__name__", "other")];
+    let err = to_prometheus_text(&data, None, &meta, &labels).unwrap_err();
+    assert!(matches!(
+        err,
+        PrometheusError::ReservedLabel { ref metric, ref label }
+            if metric == "latency" && label == "__name__"
+    ));
+
+    let mut meta = HashMap::new();
+    meta.insert(
+        "latency",
+        MetricDescriptor {
+            metric_type: MetricType::Histogram,
+            labels: vec![("le", "1")],
+            ..Default::default()
+        },
+    );
+    let labels: Vec<(&str, &str)> = vec![];
+    let err = to_prometheus_text(&data, None, &meta, &labels).unwrap_err();
+    assert!(matches!(
+        err,
+        PrometheusError::LabelConflict { ref label, metric_type: MetricType::Histogram, .. }
+            if label == "le"
+    ));
+
+    let mut meta = HashMap::new();
+    meta.insert(
+        "latency",
+        MetricDescriptor {
+            metric_type: MetricType::Summary,
+            ..Default::default()
+        },
+    );
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    serializer.set_current_labels(vec![("quantile".to_string(), "0.5".to_string())]);
+    let err = data.serialize(&mut serializer).unwrap_err();
+    assert!(matches!(err, PrometheusError::LabelConflict { ref label, .. } if label == "quantile"));
+
+    // `le` is only generated for histograms, so it's allowed on other types.
+    let labels = vec![("le", "1")];
+    let output = to_prometheus_text(&data, None, &HashMap::new(), &labels).unwrap();
+    assert_eq!(output, "# TYPE latency untyped\nlatency{le=\"1\"} 0.5\n");
+}
+
+#[test]
+#[allow(clippy::too_many_lines)]
+fn openmetrics_types() {
+    #[derive(Serialize)]
+    struct Build {
+        version: &'static str,
+        commit: &'static str,
+    }
+
+    #[derive(Serialize)]
+    enum Mode {
+        #[allow(dead_code)]
+        Leader,
+        Follower,
+    }
+
+    #[derive(Serialize)]
+    struct Features {
+        tls: bool,
+        compression: bool,
+    }
+
+    #[derive(Serialize)]
+    struct Data {
+        build: Build,
+        mode: Mode,
+        features: Features,
+        requests_total: u64,
+        other: u32,
+    }
+
+    let mut meta = HashMap::new();
+    meta.insert(
+        "build",
+        MetricDescriptor {
+            metric_type: MetricType::Info,
+            help: "Build information",
+            ..Default::default()
+        },
+    );
+    meta.insert(
+        "mode",
+        MetricDescriptor {
+            metric_type: MetricType::StateSet,
+            ..Default::default()
+        },
+    );
+    meta.insert(
+        "features",
+        MetricDescriptor {
+            metric_type: MetricType::StateSet,
+            ..Default::default()
+        },
+    );
+    meta.insert(
+        "requests_total",
+        MetricDescriptor {
+            metric_type: MetricType::Counter,
+            ..Default::default()
+        },
+    );
+    meta.insert(
+        "other",
+        MetricDescriptor {
+            metric_type: MetricType::Unknown,
+            ..Default::default()
+        },
+    );
+
+    let data = Data {
+        build: Build {
+            version: "1.2.3",
+            commit: "abc",
+        },
+        mode: Mode::Follower,
+        features: Features {
+            tls: true,
+            compression: false,
+        },
+        requests_total: 7,
+        other: 1,
+    };
+
+    let labels: Vec<(&str, &str)> = vec![];
+    let output = to_prometheus_text(&data, None, &meta, &labels).unwrap();
+    let expected = indoc! {"
+        # HELP build_info Build information
+        # TYPE build_info gauge
+        build_info{version=\"1.2.3\",commit=\"abc\"} 1
+
+        # TYPE mode gauge
+        mode{mode=\"Follower\"} 1
+
+        # TYPE features gauge
+        features{features=\"tls\"} 1
+        features{features=\"compression\"} 0
+
+        # TYPE requests_total counter
+        requests_total 7
+
+        # TYPE other untyped
+        other 1
+    "};
+    assert_eq!(output, expected);
+
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    serializer.set_format(TextFormat::OpenMetrics);
+    data.serialize(&mut serializer).unwrap();
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    let output = String::from_utf8(buf).unwrap();
+    let expected = indoc! {"
+        # HELP build Build information
+        # TYPE build info
+        build_info{version=\"1.2.3\",commit=\"abc\"} 1
+        # TYPE mode stateset
+        mode{mode=\"Follower\"} 1
+        # TYPE features stateset
+        features{features=\"tls\"} 1
+        features{features=\"compression\"} 0
+        # TYPE requests counter
+        requests_total 7
+        # TYPE other unknown
+        other 1
+        # EOF
+    "};
+    assert_eq!(output, expected);
+    let _parsed = parse_openmetrics(&output).unwrap();
+
+    assert_eq!(
+        MetricType::GaugeHistogram.type_name(TextFormat::Prometheus),
+        "histogram"
+    );
+    assert_eq!(
+        MetricType::GaugeHistogram.type_name(TextFormat::OpenMetrics),
+        "gaugehistogram"
+    );
+
+    // Info labels can't repeat an inherited label.
+    let err = to_prometheus_text(&data, None, &meta, [("version", "common")]).unwrap_err();
+    assert!(matches!(
+        err,
+        PrometheusError::LabelConflict { ref metric, ref label, .. }
+            if metric == "build_info" && label == "version"
+    ));
+}
+
+#[test]
+fn help_escaping() {
+    #[derive(Serialize)]
+    struct Data {
+        paths: u32,
+    }
+
+    let meta = HashMap::from([(
+        "paths",
+        MetricDescriptor {
+            metric_type: MetricType::Gauge,
+            help: "Paths like \"C:\\data\"\nper disk",
+            ..Default::default()
+        },
+    )]);
+    let labels: Vec<(&str, &str)> = vec![];
+    let output = to_prometheus_text(&Data { paths: 2 }, None, &meta, &labels).unwrap();
+    assert_eq!(
+        output,
+        indoc! {r#"
+            # HELP paths Paths like "C:\\data"\nper disk
+            # TYPE paths gauge
+            paths 2
+        "#}
+    );
+
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    serializer.set_format(TextFormat::OpenMetrics);
+    Data { paths: 2 }.serialize(&mut serializer).unwrap();
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    let output = String::from_utf8(buf).unwrap();
+    assert_eq!(
+        output,
+        indoc! {r#"
+            # HELP paths Paths like \"C:\\data\"\nper disk
+            # TYPE paths gauge
+            paths 2
+            # EOF
+        "#}
+    );
+    parse_openmetrics(&output).unwrap();
+}
+
+#[test]
+fn strict_counters() {
+    #[derive(Serialize)]
+    struct Data {
+        requests: i64,
+        errors_total: u64,
+        temperature: f64,
+    }
+
+    let mut meta = HashMap::new();
+    meta.insert(
+        "requests",
+        MetricDescriptor {
+            metric_type: MetricType::Counter,
+            ..Default::default()
+        },
+    );
+    meta.insert(
+        "errors_total",
+        MetricDescriptor {
+            metric_type: MetricType::Counter,
+            ..Default::default()
+        },
+    );
+    let labels: Vec<(&str, &str)> = vec![];
+
+    let data = Data {
+        requests: 5,
+        errors_total: 1,
+        temperature: f64::NEG_INFINITY,
+    };
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    serializer.set_strict_counters(true);
+    data.serialize(&mut serializer).unwrap();
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    let expected = indoc! {"
+        # TYPE requests_total counter
+        requests_total 5
+
+        # TYPE errors_total counter
+        errors_total 1
+
+        # TYPE temperature untyped
+        temperature -Inf
+    "};
+    assert_eq!(String::from_utf8(buf).unwrap(), expected);
+
+    let data = Data {
+        requests: -1,
+        errors_total: 1,
+        temperature: 20.0,
+    };
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    serializer.set_strict_counters(true);
+    let err = data.serialize(&mut serializer).unwrap_err();
+    assert!(matches!(
+        err,
+        PrometheusError::InvalidCounterValue { ref metric, ref value }
+            if metric == "requests_total" && value == "-1"
+    ));
+
+    // Without strict counters, the value is passed through as is.
+    let output = to_prometheus_text(&data, None, &meta, &labels).unwrap();
+    assert!(output.contains("requests -1\n"));
+}
+
+#[test]
+fn units() {
+    #[derive(Serialize)]
+    struct Data {
+        uptime: Duration,
+        memory_bytes: u64,
+        cpu_total: f64,
+    }
+
+    let mut meta = HashMap::new();
+    meta.insert(
+        "uptime",
+        MetricDescriptor {
+            metric_type: MetricType::Gauge,
+            unit: Some(MetricUnit::Seconds),
+            ..Default::default()
+        },
+    );
+    meta.insert(
+        "memory_bytes",
+        MetricDescriptor {
+            metric_type: MetricType::Gauge,
+            unit: Some(MetricUnit::Bytes),
+            ..Default::default()
+        },
+    );
+    meta.insert(
+        "cpu_total",
+        MetricDescriptor {
+            metric_type: MetricType::Counter,
+            help: "CPU time",
+            unit: Some(MetricUnit::Seconds),
+            ..Default::default()
+        },
+    );
+    let labels: Vec<(&str, &str)> = vec![];
+    let data = Data {
+        uptime: Duration::from_millis(1500),
+        memory_bytes: 1024,
+        cpu_total: 2.5,
+    };
+
+    let output = to_prometheus_text(&data, Some("app"), &meta, &labels).unwrap();
+    let expected = indoc! {"
+        # TYPE app_uptime_seconds gauge
+        app_uptime_seconds 1.5
+
+        # TYPE app_memory_bytes gauge
+        app_memory_bytes 1024
+
+        # HELP app_cpu_seconds_total CPU time
+        # TYPE app_cpu_seconds_total counter
+        app_cpu_seconds_total 2.5
+    "};
+    assert_eq!(output, expected);
+
+    let mut serializer = PrometheusSerializer::new(Some("app"), &meta, &labels);
+    serializer.set_format(TextFormat::OpenMetrics);
+    data.serialize(&mut serializer).unwrap();
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    let output = String::from_utf8(buf).unwrap();
+    let expected = indoc! {"
+        # TYPE app_uptime_seconds gauge
+        # UNIT app_uptime_seconds seconds
+        app_uptime_seconds 1.5
+        # TYPE app_memory_bytes gauge
+        # UNIT app_memory_bytes bytes
+        app_memory_bytes 1024
+        # HELP app_cpu_seconds CPU time
+        # TYPE app_cpu_seconds counter
+        # UNIT app_cpu_seconds seconds
+        app_cpu_seconds_total 2.5
+        # EOF
+    "};
+    assert_eq!(output, expected);
+    let _parsed = parse_openmetrics(&output).unwrap();
+
+    // OpenMetrics counter samples have the `_total` suffix even if the metric lacks it.
+    let restarts = HashMap::from([("restarts", crate::Counter::new(3))]);
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    serializer.set_format(TextFormat::OpenMetrics);
+    restarts.serialize(&mut serializer).unwrap();
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    let output = String::from_utf8(buf).unwrap();
+    let expected = indoc! {"
+        # TYPE restarts counter
+        restarts_total 3
+        # EOF
+    "};
+    assert_eq!(output, expected);
+    let _parsed = parse_openmetrics(&output).unwrap();
+}
+
+#[test]
+fn transforms() {
+    #[derive(Serialize)]
+    struct Data {
+        latency_ms: u64,
+        cache_kib: u32,
+        cpu_percent: f64,
+        frequency: f64,
+        offset: i32,
+    }
+
+    let descriptor = |rename, unit, transforms| MetricDescriptor {
+        metric_type: MetricType::Gauge,
+        rename,
+        unit,
+        transforms,
+        ..Default::default()
+    };
+    let meta = HashMap::from([
+        (
+            "latency_ms",
+            descriptor(
+                Some("latency"),
+                Some(MetricUnit::Seconds),
+                vec![Transform::Scale(0.001)],
+            ),
+        ),
+        (
+            "cache_kib",
+            descriptor(
+                Some("cache"),
+                Some(MetricUnit::Bytes),
+                vec![Transform::Scale(1024.0)],
+            ),
+        ),
+        (
+            "cpu_percent",
+            descriptor(
+                Some("cpu"),
+                Some(MetricUnit::Ratio),
+                vec![Transform::Scale(0.01), Transform::Clamp(0.0, 1.0)],
+            ),
+        ),
+        (
+            "frequency",
+            descriptor(
+                Some("period"),
+                Some(MetricUnit::Seconds),
+                vec![Transform::Invert],
+            ),
+        ),
+        (
+            "offset",
+            descriptor(None, None, vec![Transform::Custom(f64::abs)]),
+        ),
+    ]);
+    let data = Data {
+        latency_ms: 250,
+        cache_kib: 4,
+        cpu_percent: 120.0,
+        frequency: 4.0,
+        offset: -3,
+    };
+
+    let labels: Vec<(&str, &str)> = vec![];
+    let output = to_prometheus_text(&data, None, &meta, &labels).unwrap();
+    assert_eq!(
+        output,
+        indoc! {"
+            # TYPE latency_seconds gauge
+            latency_seconds 0.25
+
+            # TYPE cache_bytes gauge
+            cache_bytes 4096
+
+            # TYPE cpu_ratio gauge
+            cpu_ratio 1
+
+            # TYPE period_seconds gauge
+            period_seconds 0.25
+
+            # TYPE offset gauge
+            offset 3
+        "}
+    );
+}
+
+#[test]
+fn filtering() {
+    #[derive(Serialize)]
+    struct Inner {
+        hits: u32,
+        misses: u32,
+        lock_debug: u32,
+    }
+
+    #[derive(Serialize)]
+    struct Data {
+        requests: u32,
+        retries: u32,
+        internal_generation: u32,
+        cache: Inner,
+    }
+
+    let data = Data {
+        requests: 10,
+        retries: 1,
+        internal_generation: 7,
+        cache: Inner {
+            hits: 5,
+            misses: 2,
+            lock_debug: 3,
+        },
+    };
+    let meta = HashMap::from([(
+        "cache_misses",
+        MetricDescriptor {
+            skip: true,
+            ..Default::default()
+        },
+    )]);
+    let labels: Vec<(&str, &str)> = vec![];
+
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    serializer.set_exclude(["internal_*", "*_debug"]);
+    data.serialize(&mut serializer).unwrap();
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        indoc! {"
+            # TYPE requests untyped
+            requests 10
+
+            # TYPE retries untyped
+            retries 1
+
+            # TYPE cache_hits untyped
+            cache_hits 5
+        "}
+    );
+
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    serializer.set_include(["requests", "cache_*"]);
+    serializer.set_exclude(["cache_lock_?ebug"]);
+    data.serialize(&mut serializer).unwrap();
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        indoc! {"
+            # TYPE requests untyped
+            requests 10
+
+            # TYPE cache_hits untyped
+            cache_hits 5
+        "}
+    );
+
+    // Skipping a struct field skips everything nested in it.
+    let meta = HashMap::from([(
+        "cache",
+        MetricDescriptor {
+            skip: true,
+            ..Default::default()
+        },
+    )]);
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    serializer.set_exclude(["internal_*"]);
+    data.serialize(&mut serializer).unwrap();
+    assert!(serializer.unused_descriptors().is_empty());
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        indoc! {"
+            # TYPE requests untyped
+            requests 10
+
+            # TYPE retries untyped
+            retries 1
+        "}
+    );
+}
+
+#[test]
+fn missing_descriptors() {
+    use crate::{Counter, MissingDescriptor};
+
+    #[derive(Serialize)]
+    struct Data {
+        requests: u32,
+        errors: u32,
+        restarts: Counter,
+    }
+
+    let data = Data {
+        requests: 10,
+        errors: 2,
+        restarts: Counter::new(1),
+    };
+    let meta = HashMap::from([
+        (
+            "requests",
+            MetricDescriptor {
+                metric_type: MetricType::Counter,
+                ..Default::default()
+            },
+        ),
+        ("reqeusts_total", MetricDescriptor::default()),
+        ("latency", MetricDescriptor::default()),
+    ]);
+    let labels: Vec<(&str, &str)> = vec![];
+
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    serializer.set_missing_descriptor(MissingDescriptor::Skip);
+    data.serialize(&mut serializer).unwrap();
+    assert_eq!(
+        serializer.unused_descriptors(),
+        ["latency", "reqeusts_total"]
+    );
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        indoc! {"
+            # TYPE requests counter
+            requests 10
+
+            # TYPE restarts counter
+            restarts 1
+        "}
+    );
+
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    serializer.set_missing_descriptor(MissingDescriptor::Error);
+    let err = data.serialize(&mut serializer).unwrap_err();
+    assert!(matches!(
+        err,
+        PrometheusError::MissingDescriptor { ref metric } if metric == "errors"
+    ));
+}
+
+#[test]
+fn lint() {
+    use crate::Diagnostic;
+
+    #[derive(Serialize)]
+    struct Data {
+        requests: u32,
+        requests_total: u32,
+        errors: u32,
+    }
+
+    let data = Data {
+        requests: 10,
+        requests_total: 12,
+        errors: 2,
+    };
+    let meta = HashMap::from([
+        (
+            "requests",
+            MetricDescriptor {
+                metric_type: MetricType::Counter,
+                rename: Some("requests_total"),
+                ..Default::default()
+            },
+        ),
+        ("requests_total", MetricDescriptor::default()),
+        ("latency", MetricDescriptor::default()),
+    ]);
+    let labels: Vec<(&str, &str)> = vec![];
+
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    data.serialize(&mut serializer).unwrap();
+    let diagnostics = serializer.lint();
+    assert_eq!(
+        diagnostics,
+        [
+            Diagnostic::UnusedDescriptor {
+                key: "latency".to_owned()
+            },
+            Diagnostic::Undescribed {
+                path: "errors".to_owned()
+            },
+            Diagnostic::NameCollision {
+                metric: "requests_total".to_owned(),
+                paths: vec!["requests".to_owned(), "requests_total".to_owned()],
+            },
+        ]
+    );
+    assert_eq!(
+        diagnostics[2].to_string(),
+        "metric `requests_total` is written by `requests`, `requests_total`"
+    );
+}
+
+#[test]
+fn path_names() {
+    use crate::FieldCase;
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Queue {
+        pending_jobs: u32,
+        #[serde(rename = "HTTPErrors")]
+        http_errors: u32,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Data {
+        work_queue: Queue,
+        #[serde(rename = "_retry__count_")]
+        retry_count: u32,
+    }
+
+    let data = Data {
+        work_queue: Queue {
+            pending_jobs: 3,
+            http_errors: 1,
+        },
+        retry_count: 2,
+    };
+    let meta = HashMap::from([(
+        "work_queue_http_errors",
+        MetricDescriptor {
+            metric_type: MetricType::Counter,
+            ..Default::default()
+        },
+    )]);
+    let labels: Vec<(&str, &str)> = vec![];
+
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    serializer.set_field_case(FieldCase::Snake);
+    serializer.set_collapse_underscores(true);
+    data.serialize(&mut serializer).unwrap();
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        indoc! {"
+            # TYPE work_queue_pending_jobs untyped
+            work_queue_pending_jobs 3
+
+            # TYPE work_queue_http_errors counter
+            work_queue_http_errors 1
+
+            # TYPE retry_count untyped
+            retry_count 2
+        "}
+    );
+
+    let mut serializer = PrometheusSerializer::new(Some("app"), &meta, &labels);
+    serializer.set_separator(":").unwrap();
+    assert!(matches!(
+        serializer.set_separator("-"),
+        Err(PrometheusError::InvalidSeparator { ref separator }) if separator == "-"
+    ));
+    assert!(serializer.set_separator("").is_err());
+    data.serialize(&mut serializer).unwrap();
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        indoc! {"
+            # TYPE app_WorkQueue:pendingJobs untyped
+            app_WorkQueue:pendingJobs 3
+
+            # TYPE app_WorkQueue:HTTPErrors untyped
+            app_WorkQueue:HTTPErrors 1
+
+            # TYPE app__retry__count_ untyped
+            app__retry__count_ 2
+        "}
+    );
+}
+
+#[test]
+#[allow(clippy::too_many_lines)]
+fn flatten_rules() {
+    use crate::Flatten;
+
+    #[derive(Serialize)]
+    struct Pool {
+        active: u32,
+        idle: u32,
+    }
+
+    #[derive(Serialize)]
+    struct Stats {
+        hits: u32,
+    }
+
+    #[derive(Serialize)]
+    struct Extra {
+        errors: u32,
+    }
+
+    #[derive(Serialize)]
+    struct Data {
+        common: Pool,
+        db_pool: Pool,
+        cache: Stats,
+        legacy: Stats,
+        #[serde(flatten)]
+        extra: Extra,
+    }
+
+    let data = Data {
+        common: Pool { active: 1, idle: 2 },
+        db_pool: Pool { active: 3, idle: 4 },
+        cache: Stats { hits: 5 },
+        legacy: Stats { hits: 6 },
+        extra: Extra { errors: 7 },
+    };
+    let meta = HashMap::from([
+        (
+            "common",
+            MetricDescriptor {
+                flatten: Flatten::Transparent,
+                ..Default::default()
+            },
+        ),
+        (
+            "db_pool",
+            MetricDescriptor {
+                flatten: Flatten::Replace("database"),
+                ..Default::default()
+            },
+        ),
+        (
+            "cache",
+            MetricDescriptor {
+                flatten: Flatten::Root("memcached"),
+                ..Default::default()
+            },
+        ),
+        (
+            "memcached_hits",
+            MetricDescriptor {
+                metric_type: MetricType::Counter,
+                ..Default::default()
+            },
+        ),
+        (
+            "legacy",
+            MetricDescriptor {
+                flatten: Flatten::Root(""),
+                ..Default::default()
+            },
+        ),
+    ]);
+    let labels: Vec<(&str, &str)> = vec![];
+
+    let mut serializer = PrometheusSerializer::new(Some("app"), &meta, &labels);
+    data.serialize(&mut serializer).unwrap();
+    assert!(serializer.unused_descriptors().is_empty());
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        indoc! {"
+            # TYPE app_active untyped
+            app_active 1
+
+            # TYPE app_idle untyped
+            app_idle 2
+
+            # TYPE app_database_active untyped
+            app_database_active 3
+
+            # TYPE app_database_idle untyped
+            app_database_idle 4
+
+            # TYPE app_memcached_hits counter
+            app_memcached_hits 5
+
+            # TYPE app_hits untyped
+            app_hits 6
+
+            # TYPE app_errors untyped
+            app_errors 7
+        "}
+    );
+
+    let meta = HashMap::from([(
+        "uptime",
+        MetricDescriptor {
+            flatten: Flatten::Transparent,
+            ..Default::default()
+        },
+    )]);
+    let err =
+        to_prometheus_text(&HashMap::from([("uptime", 1)]), None, &meta, &labels).unwrap_err();
+    assert!(matches!(err, PrometheusError::EmptyPath));
+}
+
+#[test]
+#[allow(clippy::too_many_lines)]
+fn serde_flatten() {
+    use crate::Diagnostic;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+    enum Method {
+        Get,
+        Post,
+    }
+
+    #[derive(Serialize)]
+    struct Io {
+        reads: u32,
+        writes: u32,
+    }
+
+    #[derive(Serialize)]
+    struct Disk {
+        #[serde(flatten)]
+        io: Io,
+        latency: Duration,
+    }
+
+    #[derive(Serialize)]
+    struct Data {
+        uptime: u32,
+        #[serde(flatten)]
+        disk: Disk,
+        #[serde(flatten)]
+        missing: Option<Io>,
+        ports: BTreeMap<u16, u32>,
+        methods: BTreeMap<&'static str, u32>,
+        #[serde(flatten)]
+        requests: BTreeMap<Method, u32>,
+        ratios: BTreeMap<String, f64>,
+    }
+
+    #[derive(Serialize)]
+    struct Flags {
+        #[serde(flatten)]
+        counts: BTreeMap<bool, u32>,
+        #[serde(flatten)]
+        ports: BTreeMap<u16, u32>,
+    }
+
+    #[derive(Serialize)]
+    struct Compound {
+        uptime: u32,
+        #[serde(flatten)]
+        pairs: BTreeMap<(u8, u8), u32>,
+    }
+
+    let data = Data {
+        uptime: 60,
+        disk: Disk {
+            io: Io {
+                reads: 1,
+                writes: 2,
+            },
+            latency: Duration::from_millis(1500),
+        },
+        missing: None,
+        ports: BTreeMap::from([(80, 3), (443, 4)]),
+        methods: BTreeMap::from([("GET /health", 5)]),
+        requests: BTreeMap::from([(Method::Get, 6), (Method::Post, 7)]),
+        ratios: BTreeMap::from([("hit-rate".to_owned(), 0.5)]),
+    };
+    let labels: Vec<(&str, &str)> = vec![];
+    let text = to_prometheus_text(&data, Some("app"), &HashMap::new(), &labels).unwrap();
+    assert_eq!(
+        text,
+        indoc! {"
+            # TYPE app_uptime untyped
+            app_uptime 60
+
+            # TYPE app_reads untyped
+            app_reads 1
+
+            # TYPE app_writes untyped
+            app_writes 2
+
+            # TYPE app_latency_seconds untyped
+            app_latency_seconds 1.5
+
+            # TYPE app_ports_80 untyped
+            app_ports_80 3
+
+            # TYPE app_ports_443 untyped
+            app_ports_443 4
+
+            # TYPE app_methods_GET__health untyped
+            app_methods_GET__health 5
+
+            # TYPE app_Get untyped
+            app_Get 6
+
+            # TYPE app_Post untyped
+            app_Post 7
+
+            # TYPE app_ratios_hit_rate untyped
+            app_ratios_hit_rate 0.5
+        "}
+    );
+
+    let flags = Flags {
+        counts: BTreeMap::from([(true, 2)]),
+        ports: BTreeMap::from([(80, 3)]),
+    };
+    let text = to_prometheus_text(&flags, None, &HashMap::new(), &labels).unwrap();
+    assert_eq!(
+        text,
+        indoc! {"
+            # TYPE true untyped
+            true 2
+
+            # TYPE _80 untyped
+            _80 3
+        "}
+    );
+
+    // Keys sanitized to the same segment overwrite each other, which lint reports.
+    let methods = BTreeMap::from([("GET x", 1), ("GET.x", 2)]);
+    let empty = HashMap::new();
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &empty, &labels);
+    methods.serialize(&mut serializer).unwrap();
+    assert_eq!(
+        serializer.lint(),
+        [
+            Diagnostic::Undescribed {
+                path: "GET_x".to_owned()
+            },
+            Diagnostic::KeyCollision {
+                path: "GET_x".to_owned(),
+                keys: vec!["GET x".to_owned(), "GET.x".to_owned()],
+            },
+        ]
+    );
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        indoc! {"
+            # TYPE GET_x untyped
+            GET_x 2
+        "}
+    );
+
+    let compound = Compound {
+        uptime: 1,
+        pairs: BTreeMap::from([((1, 2), 3)]),
+    };
+    let text = to_prometheus_text(&compound, None, &HashMap::new(), &labels).unwrap();
+    assert_eq!(
+        text,
+        indoc! {"
+            # TYPE uptime untyped
+            uptime 1
+        "}
+    );
+}
+
+#[test]
+fn durations_and_system_times() {
+    // User types named like the std ones are only time values with the std fields.
+    mod custom {
+        #[derive(serde::Serialize)]
+        pub struct Duration {
+            pub ms: u64,
+        }
+
+        #[derive(serde::Serialize)]
+        pub struct SystemTime {
+            pub secs_since_epoch: u64,
+            pub offset: i32,
+        }
+
+        pub mod window {
+            #[derive(serde::Serialize)]
+            pub struct Duration {
+                pub start: u64,
+                pub end: u64,
+            }
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Custom {
+        timeout: custom::Duration,
+        window: custom::window::Duration,
+    }
+
+    #[derive(Serialize)]
+    struct Mismatched {
+        started: custom::SystemTime,
+    }
+
+    #[derive(Serialize)]
+    struct Data {
+        uptime: Duration,
+        timeout_seconds: Option<Duration>,
+        last_success: SystemTime,
+        busy_total: Duration,
+    }
+
+    let mut meta = HashMap::new();
+    meta.insert(
+        "busy_total",
+        MetricDescriptor {
+            metric_type: MetricType::Counter,
+            ..Default::default()
+        },
+    );
+    let labels: Vec<(&str, &str)> = vec![];
+    let data = Data {
+        uptime: Duration::new(90, 250_000_000),
+        timeout_seconds: Some(Duration::from_secs(5)),
+        last_success: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        busy_total: Duration::from_millis(20),
+    };
+
+    let output = to_prometheus_text(&data, None, &meta, &labels).unwrap();
+    let expected = indoc! {"
+        # TYPE uptime_seconds untyped
+        uptime_seconds 90.25
+
+        # TYPE timeout_seconds untyped
+        timeout_seconds 5
+
+        # TYPE last_success_seconds untyped
+        last_success_seconds 1700000000
+
+        # TYPE busy_seconds_total counter
+        busy_seconds_total 0.02
+    "};
+    assert_eq!(output, expected);
+
+    let custom = Custom {
+        timeout: custom::Duration { ms: 250 },
+        window: custom::window::Duration { start: 3, end: 4 },
+    };
+    let output = to_prometheus_text(&custom, None, &meta, &labels).unwrap();
+    assert_eq!(
+        output,
+        indoc! {"
+            # TYPE timeout_ms untyped
+            timeout_ms 250
+
+            # TYPE window_start untyped
+            window_start 3
+
+            # TYPE window_end untyped
+            window_end 4
+        "}
+    );
+    let mismatched = Mismatched {
+        started: custom::SystemTime {
+            secs_since_epoch: 1,
+            offset: 2,
+        },
+    };
+    let output = to_prometheus_text(&mismatched, None, &meta, &labels).unwrap();
+    assert_eq!(
+        output,
+        indoc! {"
+            # TYPE started_secs_since_epoch untyped
+            started_secs_since_epoch 1
+
+            # TYPE started_offset untyped
+            started_offset 2
+        "}
+    );
+}
+
+#[test]
+fn serialize_many_values() {
+    #[derive(Serialize)]
+    struct Usage {
+        requests: u32,
+        storage_bytes: u64,
+    }
+
+    let meta = HashMap::from([(
+        "requests",
+        MetricDescriptor {
+            metric_type: MetricType::Counter,
+            help: "Requests",
+            ..Default::default()
+        },
+    )]);
+    let tenant = |name: &str| vec![("tenant".to_owned(), name.to_owned())];
+
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, [("app", "myapp")]);
+    serializer
+        .serialize_with_labels(
+            &Usage {
+                requests: 5,
+                storage_bytes: 1024,
+            },
+            tenant("acme"),
+        )
+        .unwrap();
+    serializer
+        .serialize_with_labels(
+            &Usage {
+                requests: 2,
+                storage_bytes: 512,
+            },
+            tenant("globex"),
+        )
+        .unwrap();
+
+    let mut other = PrometheusSerializer::new(None::<&str>, &meta, [("app", "myapp")]);
+    other.set_current_labels(tenant("initech"));
+    Usage {
+        requests: 9,
+        storage_bytes: 0,
+    }
+    .serialize(&mut other)
+    .unwrap();
+    serializer.extend(other).unwrap();
+
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        indoc! {r#"
+            # HELP requests Requests
+            # TYPE requests counter
+            requests{tenant="acme",app="myapp"} 5
+            requests{tenant="globex",app="myapp"} 2
+            requests{tenant="initech",app="myapp"} 9
+
+            # TYPE storage_bytes untyped
+            storage_bytes{tenant="acme",app="myapp"} 1024
+            storage_bytes{tenant="globex",app="myapp"} 512
+            storage_bytes{tenant="initech",app="myapp"} 0
+        "#}
+    );
+
+    // Writing a series again would overwrite the earlier value.
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, [("app", "myapp")]);
+    let usage = Usage {
+        requests: 1,
+        storage_bytes: 2,
+    };
+    serializer
+        .serialize_with_labels(&usage, tenant("acme"))
+        .unwrap();
+    let err = serializer
+        .serialize_with_labels(&usage, tenant("acme"))
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        PrometheusError::DuplicateSeries { ref series }
+            if series == r#"requests{tenant="acme",app="myapp"}"#
+    ));
+}
+
+#[test]
+fn atomic_metrics() {
+    use crate::{Counter, FloatGauge, Gauge};
+    use std::sync::Arc;
+
+    #[derive(Serialize, Default)]
+    struct State {
+        requests_total: Counter,
+        in_flight: Gauge,
+        load: FloatGauge,
+        queue: Gauge,
+    }
+
+    let meta = HashMap::from([
+        (
+            "requests_total",
+            MetricDescriptor {
+                help: "Requests",
+                ..Default::default()
+            },
+        ),
+        (
+            "queue",
+            MetricDescriptor {
+                metric_type: MetricType::Untyped,
+                rename: Some("queue_length"),
+                ..Default::default()
+            },
+        ),
+    ]);
+    let state = Arc::new(State::default());
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let state = Arc::clone(&state);
+            std::thread::spawn(move || {
+                for _ in 0..100 {
+                    state.requests_total.inc();
+                    state.in_flight.inc();
+                    state.load.add(0.25);
+                }
+                state.in_flight.sub(99);
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    state.queue.set(-3);
+
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, Vec::<(&str, &str)>::new());
+    serializer.set_strict_counters(true);
+    state.serialize(&mut serializer).unwrap();
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        indoc! {"
+            # HELP requests_total Requests
+            # TYPE requests_total counter
+            requests_total 400
+
+            # TYPE in_flight gauge
+            in_flight 4
+
+            # TYPE load gauge
+            load 100
+
+            # TYPE queue_length gauge
+            queue_length -3
+        "}
+    );
+}
+
+#[test]
+fn histograms() {
+    use crate::Histogram;
+
+    #[derive(Serialize)]
+    struct Data {
+        latency: Histogram,
+        queue_depth: Histogram,
+    }
+
+    let data = Data {
+        latency: Histogram::exponential(0.25, 2.0, 3),
+        queue_depth: Histogram::new([10.0, 1.0, 5.0, f64::INFINITY]),
+    };
+    for value in [0.1, 0.5, 0.5, 3.0] {
+        data.latency.observe(value);
+    }
+    data.queue_depth.observe(5.0);
+    assert_eq!(data.latency.buckets(), [0.25, 0.5, 1.0]);
+    assert_eq!(Histogram::linear(1.0, 2.0, 3).buckets(), [1.0, 3.0, 5.0]);
+
+    let meta = HashMap::from([
+        (
+            "latency",
+            MetricDescriptor {
+                help: "Request latency",
+                unit: Some(MetricUnit::Seconds),
+                ..Default::default()
+            },
+        ),
+        (
+            "queue_depth",
+            MetricDescriptor {
+                metric_type: MetricType::GaugeHistogram,
+                ..Default::default()
+            },
+        ),
+    ]);
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, [("app", "myapp")]);
+    serializer.set_sorted(true);
+    serializer.set_format(TextFormat::OpenMetrics);
+    data.serialize(&mut serializer).unwrap();
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    let output = String::from_utf8(buf).unwrap();
+    assert_eq!(
+        output,
+        indoc! {r#"
+            # HELP latency_seconds Request latency
+            # TYPE latency_seconds histogram
+            # UNIT latency_seconds seconds
+            latency_seconds_bucket{app="myapp",le="0.25"} 1
+            latency_seconds_bucket{app="myapp",le="0.5"} 3
+            latency_seconds_bucket{app="myapp",le="1"} 3
+            latency_seconds_bucket{app="myapp",le="+Inf"} 4
+            latency_seconds_sum{app="myapp"} 4.1
+            latency_seconds_count{app="myapp"} 4
+            # TYPE queue_depth gaugehistogram
+            queue_depth_bucket{app="myapp",le="1"} 0
+            queue_depth_bucket{app="myapp",le="5"} 1
+            queue_depth_bucket{app="myapp",le="10"} 1
+            queue_depth_bucket{app="myapp",le="+Inf"} 1
+            queue_depth_gsum{app="myapp"} 5
+            queue_depth_gcount{app="myapp"} 1
+            # EOF
+        "#}
+    );
+    parse_prometheus(&to_prometheus_text(&data, None, &meta, [("app", "myapp")]).unwrap()).unwrap();
+
+    let text = to_prometheus_text(&data, None, &HashMap::new(), [("le", "1")]);
+    assert!(matches!(
+        text,
+        Err(PrometheusError::LabelConflict { ref label, .. }) if label == "le"
+    ));
+
+    // NaN observations only count in the `+Inf` bucket.
+    let nan = HashMap::from([("ratio", Histogram::new([0.5]))]);
+    nan["ratio"].observe(f64::NAN);
+    let labels: Vec<(&str, &str)> = vec![];
+    assert_eq!(
+        to_prometheus_text(&nan, None, &HashMap::new(), &labels).unwrap(),
+        indoc! {r#"
+            # TYPE ratio histogram
+            ratio_bucket{le="0.5"} 0
+            ratio_bucket{le="+Inf"} 1
+            ratio_sum NaN
+            ratio_count 1
+        "#}
+    );
+}
+
+#[test]
+fn summaries() {
+    use crate::Summary;
+    use std::time::Instant;
+
+    #[derive(Serialize)]
+    struct Data {
+        latency: Summary,
+        idle: Summary,
+    }
+
+    let data = Data {
+        latency: Summary::new([(0.5, 0.05), (0.9, 0.01), (0.99, 0.001)]),
+        idle: Summary::new([(0.5, 0.05)]),
+    };
+    for value in 1..=1000 {
+        data.latency.observe(f64::from(value));
+    }
+
+    let output = to_prometheus_text(&data, None, &HashMap::new(), [("app", "myapp")]).unwrap();
+    parse_prometheus(&output).unwrap();
+    let value = |series: &str| -> f64 {
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+    assert!(output.contains("# TYPE latency summary\n"));
+    assert!((450.0..=550.0).contains(&value(r#"latency{app="myapp",quantile="0.5"}"#)));
+    assert!((890.0..=910.0).contains(&value(r#"latency{app="myapp",quantile="0.9"}"#)));
+    assert!((989.0..=991.0).contains(&value(r#"latency{app="myapp",quantile="0.99"}"#)));
+    assert!((value(r#"latency_sum{app="myapp"}"#) - 500_500.0).abs() < f64::EPSILON);
+    assert!((value(r#"latency_count{app="myapp"}"#) - 1000.0).abs() < f64::EPSILON);
+    assert!(output.contains("idle{app=\"myapp\",quantile=\"0.5\"} NaN\n"));
+    assert!(output.contains("idle_count{app=\"myapp\"} 0\n"));
+
+    // Observations older than the window no longer count towards the quantiles.
+    let window = Duration::from_mins(1);
+    let recent = Summary::with_window([(0.5, 0.05)], window, 2);
+    let start = Instant::now();
+    recent.observe_at(2.0, start);
+    assert_eq!(recent.quantiles_at(start + window / 4), vec![(0.5, 2.0)]);
+    recent.observe_at(4.0, start + window * 3 / 4);
+    assert_eq!(
+        recent.quantiles_at(start + window * 5 / 4),
+        vec![(0.5, 4.0)]
+    );
+    assert!(recent.quantiles_at(start + window * 3)[0].1.is_nan());
+    assert_eq!(recent.count(), 2);
+}
+
+#[test]
+fn native_histograms() {
+    use crate::NativeHistogram;
+
+    #[derive(Serialize)]
+    struct Data {
+        latency: NativeHistogram,
+    }
+
+    let data = Data {
+        latency: NativeHistogram::with_zero_threshold(0, 0.001),
+    };
+    for value in [0.0, 1.0, 3.0, 3.5, -1.5] {
+        data.latency.observe(value);
+    }
+    assert_eq!(NativeHistogram::new(12).schema(), 8);
+
+    // Text formats fall back to one classic bucket per populated native bucket.
+    let labels: Vec<(&str, &str)> = vec![];
+    let output = to_prometheus_text(&data, None, &HashMap::new(), &labels).unwrap();
+    assert_eq!(
+        output,
+        indoc! {r#"
+            # TYPE latency histogram
+            latency_bucket{le="-1"} 1
+            latency_bucket{le="0.001"} 2
+            latency_bucket{le="1"} 3
+            latency_bucket{le="4"} 5
+            latency_bucket{le="+Inf"} 5
+            latency_sum 6
+            latency_count 5
+        "#}
+    );
+
+    let meta = HashMap::new();
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    serializer.set_native_fallback(NativeFallback::Inf);
+    data.serialize(&mut serializer).unwrap();
+    let mut buf = Vec::new();
+    serializer.finish(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        indoc! {r#"
+            # TYPE latency histogram
+            latency_bucket{le="+Inf"} 5
+            latency_sum 6
+            latency_count 5
+        "#}
+    );
+}
+
+#[test]
+fn registry() {
+    use crate::Registry;
+
+    #[derive(Serialize)]
+    struct Http {
+        requests_total: u64,
+    }
+
+    #[derive(Serialize)]
+    struct Queue {
+        depth: u64,
+    }
+
+    let counter = || {
+        HashMap::from([(
+            "requests_total",
+            MetricDescriptor {
+                metric_type: MetricType::Counter,
+                help: "Requests",
+                ..Default::default()
+            },
+        )])
+    };
+    let mut registry = Registry::new();
+    registry.register("api", None::<&str>, counter(), [("server", "api")], || {
+        Http { requests_total: 3 }
+    });
+    registry.register(
+        "queue",
+        Some("jobs"),
+        HashMap::new(),
+        [("queue", "default")],
+        || Queue { depth: 7 },
+    );
+    registry.register(
+        "admin",
+        None::<&str>,
+        counter(),
+        [("server", "admin")],
+        || Http { requests_total: 1 },
+    );
+
+    let mut buf = Vec::new();
+    registry.gather().unwrap().finish(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        indoc! {r#"
+            # HELP requests_total Requests
+            # TYPE requests_total counter
+            requests_total{server="api"} 3
+            requests_total{server="admin"} 1
+
+            # TYPE jobs_depth untyped
+            jobs_depth{queue="default"} 7
+        "#}
+    );
+
+    // Replacing a source keeps its position, and families must agree on their type.
+    registry.register(
+        "api",
+        None::<&str>,
+        HashMap::new(),
+        [("server", "api")],
+        || Http { requests_total: 4 },
+    );
+    assert!(matches!(
+        registry.gather(),
+        Err(PrometheusError::TypeConflict {
+            first: MetricType::Untyped,
+            second: MetricType::Counter,
+            ..
+        })
+    ));
+    assert!(registry.unregister("api"));
+    assert!(!registry.unregister("api"));
+
+    // Sources writing the same series would overwrite each other.
+    registry.register("up", None::<&str>, HashMap::new(), [("job", "a")], || {
+        HashMap::from([("up", 1)])
+    });
+    registry.register(
+        "up_again",
+        None::<&str>,
+        HashMap::new(),
+        [("job", "a")],
+        || HashMap::from([("up", 2)]),
+    );
+    assert!(matches!(
+        registry.gather(),
+        Err(PrometheusError::DuplicateSeries { ref series }) if series == "up{job=\"a\"}"
+    ));
+    assert!(registry.unregister("up_again"));
+    assert!(registry.unregister("up"));
+    let mut buf = Vec::new();
+    registry.gather().unwrap().finish(&mut buf).unwrap();
+    assert!(
+        String::from_utf8(buf)
+            .unwrap()
+            .starts_with("# TYPE jobs_depth")
+    );
+}
+
+#[cfg(feature = "protobuf")]
+#[test]
+fn protobuf_encoding() {
+    use crate::proto::{Counter, Gauge, ProtoMetricFamily, ProtoMetricType, Untyped};
+    use prost::Message;
+
+    #[derive(Serialize)]
+    struct Data {
+        requests_total: u64,
+        temperature: f64,
+        other: i32,
+    }
+
+    let mut meta = HashMap::new();
+    meta.insert(
+        "requests_total",
+        MetricDescriptor {
+            metric_type: MetricType::Counter,
+            help: "Requests",
+            ..Default::default()
+        },
+    );
+    meta.insert(
+        "temperature",
+        MetricDescriptor {
+            metric_type: MetricType::Gauge,
+            unit: Some(MetricUnit::Celsius),
+            ..Default::default()
+        },
+    );
+    let labels = vec![("app", "myapp")];
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    Data {
+        requests_total: 12,
+        temperature: 21.5,
+        other: -3,
+    }
+    .serialize(&mut serializer)
+    .unwrap();
+    let mut buf = Vec::new();
+    serializer.finish_protobuf(&mut buf).unwrap();
+
+    let mut buf = buf.as_slice();
+    let mut families = Vec::new();
+    while !buf.is_empty() {
+        families.push(ProtoMetricFamily::decode_length_delimited(&mut buf).unwrap());
+    }
+    assert_eq!(families.len(), 3);
+
+    assert_eq!(families[0].name, "requests_total");
+    assert_eq!(families[0].help, "Requests");
+    assert_eq!(families[0].r#type, ProtoMetricType::Counter as i32);
+    let metric = &families[0].metric[0];
+    assert_eq!(metric.label[0].name, "app");
+    assert_eq!(metric.label[0].value, "myapp");
+    assert_eq!(metric.counter, Some(Counter { value: 12.0 }));
+
+    assert_eq!(families[1].name, "temperature_celsius");
+    assert_eq!(families[1].unit, "celsius");
+    assert_eq!(families[1].metric[0].gauge, Some(Gauge { value: 21.5 }));
+
+    assert_eq!(families[2].r#type, ProtoMetricType::Untyped as i32);
+    assert_eq!(families[2].metric[0].untyped, Some(Untyped { value: -3.0 }));
+}
+
+#[cfg(feature = "protobuf")]
+#[test]
+fn protobuf_native_histograms() {
+    use crate::NativeHistogram;
+    use crate::proto::{BucketSpan, ProtoMetricFamily, ProtoMetricType};
+    use prost::Message;
+
+    #[derive(Serialize)]
+    struct Data {
+        latency: NativeHistogram,
+        idle: NativeHistogram,
+    }
+
+    let data = Data {
+        latency: NativeHistogram::with_zero_threshold(0, 0.001),
+        idle: NativeHistogram::new(3),
+    };
+    for value in [0.0, 1.0, 3.0, 3.5, -1.5] {
+        data.latency.observe(value);
+    }
+
+    let meta = HashMap::new();
+    let labels: Vec<(&str, &str)> = vec![];
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    data.serialize(&mut serializer).unwrap();
+    let mut buf = Vec::new();
+    serializer.finish_protobuf(&mut buf).unwrap();
+    let mut buf = buf.as_slice();
+    let latency = ProtoMetricFamily::decode_length_delimited(&mut buf).unwrap();
+    let idle = ProtoMetricFamily::decode_length_delimited(&mut buf).unwrap();
+
+    assert_eq!(latency.r#type, ProtoMetricType::Histogram as i32);
+    let histogram = latency.metric[0].histogram.as_ref().unwrap();
+    assert_eq!(histogram.schema, 0);
+    assert_eq!(histogram.zero_count, 1);
+    assert_eq!(histogram.sample_count, 5);
+    assert!(histogram.bucket.is_empty());
+    let span = |offset, length| BucketSpan { offset, length };
+    assert_eq!(histogram.positive_span, vec![span(0, 1), span(1, 1)]);
+    assert_eq!(histogram.positive_delta, vec![1, 1]);
+    assert_eq!(histogram.negative_span, vec![span(1, 1)]);
+    assert_eq!(histogram.negative_delta, vec![1]);
+
+    let histogram = idle.metric[0].histogram.as_ref().unwrap();
+    assert_eq!(histogram.schema, 3);
+    assert_eq!(histogram.positive_span, vec![span(0, 0)]);
+    assert!(histogram.positive_delta.is_empty());
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn http_service() {
+    use crate::MetricsService;
+    use flate2::read::GzDecoder;
+    use http::{Request, header};
+    use http_body_util::BodyExt;
+    use std::io::Read;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+    use tower_service::Service;
+
+    #[derive(Serialize)]
+    struct Data {
+        requests_total: u64,
+    }
+
+    fn get(
+        service: &mut MetricsService<impl Fn() -> Data>,
+        headers: &[(header::HeaderName, &str)],
+    ) -> (http::response::Parts, Vec<u8>) {
+        let mut request = Request::builder().uri("/metrics");
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        let response = service
+            .call(request.body(()).unwrap())
+            .into_inner()
+            .unwrap();
+        let (parts, body) = response.into_parts();
+        let mut collect = pin!(body.collect());
+        let Poll::Ready(Ok(body)) = collect
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        else {
+            panic!("body not ready");
+        };
+        (parts, body.to_bytes().to_vec())
+    }
+
+    let mut meta = HashMap::new();
+    meta.insert(
+        "requests_total",
+        MetricDescriptor {
+            metric_type: MetricType::Counter,
+            ..Default::default()
+        },
+    );
+    let labels: Vec<(&str, &str)> = vec![];
+    let mut service =
+        MetricsService::new(Some("app"), meta, &labels, || Data { requests_total: 3 });
+
+    let (parts, body) = get(&mut service, &[]);
+    assert_eq!(parts.status, 200);
+    assert_eq!(
+        parts.headers[header::CONTENT_TYPE],
+        crate::PROMETHEUS_CONTENT_TYPE
+    );
+    assert_eq!(
+        String::from_utf8(body).unwrap(),
+        "# TYPE app_requests_total counter\napp_requests_total 3\n"
+    );
+
+    let (parts, body) = get(
+        &mut service,
+        &[
+            (
+                header::ACCEPT,
+                "application/openmetrics-text;version=1.0.0;q=0.9, text/plain;q=0.5",
+            ),
+            (header::ACCEPT_ENCODING, "gzip, deflate"),
+        ],
+    );
+    assert_eq!(
+        parts.headers[header::CONTENT_TYPE],
+        crate::OPENMETRICS_CONTENT_TYPE
+    );
+    assert_eq!(parts.headers[header::CONTENT_ENCODING], "gzip");
+    let mut text = String::new();
+    GzDecoder::new(body.as_slice())
+        .read_to_string(&mut text)
+        .unwrap();
+    assert_eq!(
+        text,
+        "# TYPE app_requests counter\napp_requests_total 3\n# EOF\n"
+    );
+
+    #[cfg(feature = "protobuf")]
+    {
+        let (parts, body) = get(
+            &mut service,
+            &[(
+                header::ACCEPT,
+                "application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=delimited;q=0.7,application/openmetrics-text;version=1.0.0;q=0.5,text/plain;version=0.0.4;q=0.3",
+            )],
+        );
+        assert_eq!(
+            parts.headers[header::CONTENT_TYPE],
+            crate::PROTOBUF_CONTENT_TYPE
+        );
+        assert!(!body.is_empty());
+    }
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn metrics_server() {
+    use crate::MetricsServer;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[derive(Serialize)]
+    struct Data {
+        requests: u64,
+    }
+
+    fn request(addr: std::net::SocketAddr, request_line: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "{request_line}\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    let requests = Arc::new(AtomicU64::new(0));
+    let counter = Arc::clone(&requests);
+    let labels = vec![("app", "myapp")];
+    let server = MetricsServer::bind(
+        "127.0.0.1:0",
+        None::<&str>,
+        HashMap::new(),
+        &labels,
+        move || Data {
+            requests: counter.fetch_add(1, Ordering::SeqCst) + 1,
+        },
+    )
+    .unwrap();
+    let addr = server.local_addr();
+
+    let response = request(addr, "GET /metrics HTTP/1.1");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n"));
+    assert!(response.ends_with("\r\n\r\n# TYPE requests untyped\nrequests{app=\"myapp\"} 1\n"));
+
+    let response = request(addr, "GET /metrics?x=1 HTTP/1.1");
+    assert!(response.ends_with("requests{app=\"myapp\"} 2\n"));
+
+    let response = request(addr, "GET /other HTTP/1.1");
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    let response = request(addr, "POST /metrics HTTP/1.1");
+    assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+
+    // Headers beyond the size limit are not waited for.
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut head = String::from("GET /metrics HTTP/1.1\r\nX-Padding: ");
+    head.push_str(&"a".repeat(8 * 1024 - head.len()));
+    stream.write_all(head.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.ends_with("requests{app=\"myapp\"} 3\n"));
+
+    server.shutdown();
+    assert!(TcpStream::connect(addr).is_err());
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+}
+
+/// Request line, headers and body of a request received by `mock_http_server`.
+#[cfg(any(feature = "pushgateway", feature = "remote-write"))]
+type MockRequest = (String, Vec<String>, Vec<u8>);
+
+/// Accepts `count` HTTP requests on a local port, answering each with `status`, and returns
+/// the received requests.
+#[cfg(any(feature = "pushgateway", feature = "remote-write"))]
+fn mock_http_server(
+    count: usize,
+    status: &'static str,
+) -> (
+    std::net::SocketAddr,
+    std::thread::JoinHandle<Vec<MockRequest>>,
+) {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for _ in 0..count {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut headers = Vec::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_owned();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':')
+                    && name.eq_ignore_ascii_case("content-length")
+                {
+                    content_length = value.trim().parse().unwrap();
+                }
+                headers.push(line);
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 {status}\r\nContent-Length: 4\r\nConnection: close\r\n\r\nnope"
+            )
+            .unwrap();
+            requests.push((request_line.trim_end().to_owned(), headers, body));
+        }
+        requests
+    });
+    (addr, handle)
+}
+
+#[cfg(feature = "pushgateway")]
+#[test]
+fn pushgateway() {
+    use crate::Pushgateway;
+
+    #[derive(Serialize)]
+    struct Data {
+        processed: u64,
+    }
+
+    let mut gateway = Pushgateway::new("http://localhost:9091/", "backup/nightly");
+    gateway.set_grouping_labels(vec![
+        ("instance".to_string(), "db 1".to_string()),
+        ("path".to_string(), "/var/lib".to_string()),
+        ("empty".to_string(), String::new()),
+    ]);
+    assert_eq!(
+        gateway.url(),
+        "http://localhost:9091/metrics/job@base64/YmFja3VwL25pZ2h0bHk=/instance/db%201/path@base64/L3Zhci9saWI=/empty@base64/="
+    );
+
+    let (addr, server) = mock_http_server(3, "200 OK");
+    let mut gateway = Pushgateway::new(format!("http://{addr}"), "backup");
+    gateway.set_grouping_labels(vec![("instance".to_string(), "db1".to_string())]);
+    let meta = HashMap::new();
+    let labels: Vec<(&str, &str)> = vec![];
+
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    Data { processed: 42 }.serialize(&mut serializer).unwrap();
+    gateway.push(serializer).unwrap();
+    // The body is always in the Prometheus text format, whatever the serializer's format.
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    serializer.set_format(TextFormat::OpenMetrics);
+    Data { processed: 43 }.serialize(&mut serializer).unwrap();
+    gateway.push_add(serializer).unwrap();
+    gateway.delete().unwrap();
+
+    let requests = server.join().unwrap();
+    assert_eq!(
+        requests[0].0,
+        "PUT /metrics/job/backup/instance/db1 HTTP/1.1"
+    );
+    assert!(
+        requests[0]
+            .1
+            .iter()
+            .any(|h| h
+                .eq_ignore_ascii_case("content-type: text/plain; version=0.0.4; charset=utf-8"))
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&requests[0].2),
+        "# TYPE processed untyped\nprocessed 42\n"
+    );
+    assert_eq!(
+        requests[1].0,
+        "POST /metrics/job/backup/instance/db1 HTTP/1.1"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&requests[1].2),
+        "# TYPE processed untyped\nprocessed 43\n"
+    );
+    assert_eq!(
+        requests[2].0,
+        "DELETE /metrics/job/backup/instance/db1 HTTP/1.1"
+    );
+
+    let (addr, server) = mock_http_server(1, "400 Bad Request");
+    let gateway = Pushgateway::new(format!("http://{addr}"), "backup");
+    let err = gateway.delete().unwrap_err();
+    assert!(
+        matches!(err, PrometheusError::Http(ref msg) if msg.contains("400") && msg.contains("nope"))
+    );
+    server.join().unwrap();
+}
+
+#[cfg(feature = "remote-write")]
+#[test]
+fn remote_write() {
+    use crate::RemoteWriteClient;
+    use crate::remote_write::{Label, RemoteMetricType, Sample, WriteRequest};
+    use prost::Message;
+
+    #[derive(Serialize)]
+    struct Data {
+        requests_total: u64,
+        temperature: f64,
+    }
+
+    let mut meta = HashMap::new();
+    meta.insert(
+        "requests_total",
+        MetricDescriptor {
+            metric_type: MetricType::Counter,
+            help: "Requests",
+            ..Default::default()
+        },
+    );
+    let labels = vec![("zone", "eu"), ("app", "myapp")];
+    let data = Data {
+        requests_total: 12,
+        temperature: 21.5,
+    };
+
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    data.serialize(&mut serializer).unwrap();
+    let timestamp = SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+    let body = serializer.finish_remote_write(timestamp).unwrap();
+    let decompressed = snap::raw::Decoder::new().decompress_vec(&body).unwrap();
+    let request = WriteRequest::decode(decompressed.as_slice()).unwrap();
+
+    let label = |name: &str, value: &str| Label {
+        name: name.to_owned(),
+        value: value.to_owned(),
+    };
+    assert_eq!(request.timeseries.len(), 2);
+    assert_eq!(
+        request.timeseries[0].labels,
+        vec![
+            label("__name__", "requests_total"),
+            label("app", "myapp"),
+            label("zone", "eu"),
+        ]
+    );
+    assert_eq!(
+        request.timeseries[0].samples,
+        vec![Sample {
+            value: 12.0,
+            timestamp: 1_700_000_000_123,
+        }]
+    );
+    assert_eq!(
+        request.timeseries[1].samples,
+        vec![Sample {
+            value: 21.5,
+            timestamp: 1_700_000_000_123,
+        }]
+    );
+    assert_eq!(request.metadata[0].metric_family_name, "requests_total");
+    assert_eq!(request.metadata[0].help, "Requests");
+    assert_eq!(request.metadata[0].r#type, RemoteMetricType::Counter as i32);
+
+    let (addr, server) = mock_http_server(1, "204 No Content");
+    let client = RemoteWriteClient::new(format!("http://{addr}/api/v1/write"));
+    let mut serializer = PrometheusSerializer::new(None::<&str>, &meta, &labels);
+    data.serialize(&mut serializer).unwrap();
+    client.send(serializer).unwrap();
+    let requests = server.join().unwrap();
+    assert_eq!(requests[0].0, "POST /api/v1/write HTTP/1.1");
+    let headers = requests[0].1.join("\n").to_ascii_lowercase();
+    assert!(headers.contains("content-encoding: snappy"));
+    assert!(headers.contains("content-type: application/x-protobuf"));
+    assert!(headers.contains("x-prometheus-remote-write-version: 0.1.0"));
+    let decompressed = snap::raw::Decoder::new()
+        .decompress_vec(&requests[0].2)
+        .unwrap();
+    let request = WriteRequest::decode(decompressed.as_slice()).unwrap();
+    assert_eq!(request.timeseries.len(), 2);
+}
+
+#[cfg(feature = "textfile")]
+#[test]
+fn textfile_writer() {
+    use crate::TextfileWriter;
+
+    #[derive(Serialize)]
+    struct Data {
+        last_success: u64,
+    }
+
+    /// Removes the directory when the test ends, even if it fails.
+    struct TempDir(std::path::PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    let dir =
+        TempDir(std::env::temp_dir().join(format!("serde_prom-textfile-{}", std::process::id())));
+    let dir = &dir.0;
+    // A run that was killed may have left the directory behind.
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    let path = dir.join("job.prom");
+    let meta = HashMap::from([(
+        "last_success",
+        MetricDescriptor {
+            metric_type: MetricType::Gauge,
+            help: "Last success",
+            ..Default::default()
+        },
+    )]);
+
+    let mut writer = TextfileWriter::new(&path);
+    writer.set_validate(true);
+    #[cfg(unix)]
+    writer.set_mode(0o640);
+    writer
+        .write(&Data { last_success: 42 }, None, &meta, [("job", "backup")])
+        .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        indoc! {r#"
+            # HELP last_success Last success
+            # TYPE last_success gauge
+            last_success{job="backup"} 42
+        "#}
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    // Invalid output leaves the existing file in place.
+    let bad_meta = HashMap::from([(
+        "last_success",
+        MetricDescriptor {
+            rename: Some("last-success"),
+            ..Default::default()
+        },
+    )]);
+    let err = writer
+        .write(
+            &Data { last_success: 43 },
+            None,
+            &bad_meta,
+            [("job", "backup")],
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        PrometheusError::InvalidExposition { line: 1, .. }
+    ));
+    assert!(std::fs::read_to_string(&path).unwrap().contains(" 42\n"));
+    assert_eq!(std::fs::read_dir(dir).unwrap().count(), 1);
+
+    // Concurrent writes each use their own temporary file, so the file is always complete.
+    std::thread::scope(|scope| {
+        for i in 0..8 {
+            let (writer, meta) = (&writer, &meta);
+            scope.spawn(move || {
+                for _ in 0..20 {
+                    writer
+                        .write(&Data { last_success: i }, None, meta, [("job", "backup")])
+                        .unwrap();
+                }
+            });
+        }
+    });
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert!(contents.starts_with("# HELP last_success Last success\n"));
+    assert_eq!(contents.lines().count(), 3);
+    assert_eq!(std::fs::read_dir(dir).unwrap().count(), 1);
+}
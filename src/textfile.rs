@@ -0,0 +1,223 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+
+use crate::{MetricDescriptor, PrometheusError, write_prometheus_text};
+
+/// Number of temporary files created by this process, making their names unique.
+static TMP_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// Writes metrics for node_exporter's textfile collector.
+///
+/// The output is written to a temporary file in the same directory and atomically renamed to
+/// the target path, so the collector never reads a half-written file. The target path should
+/// have the `.prom` extension, as the collector ignores other files.
+#[derive(Debug, Clone)]
+pub struct TextfileWriter {
+    path: PathBuf,
+    #[cfg(unix)]
+    mode: Option<u32>,
+    validate: bool,
+}
+
+impl TextfileWriter {
+    /// Create a new writer for the file at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        TextfileWriter {
+            path: path.into(),
+            #[cfg(unix)]
+            mode: None,
+            validate: false,
+        }
+    }
+
+    /// Set the permissions (e.g. `0o644`) of the written file.
+    #[cfg(unix)]
+    pub fn set_mode(&mut self, mode: u32) {
+        self.mode = Some(mode);
+    }
+
+    /// Check that the output is well-formed before replacing the file. When the check fails,
+    /// the existing file is left untouched.
+    pub fn set_validate(&mut self, validate: bool) {
+        self.validate = validate;
+    }
+
+    /// Serialize `value` and atomically replace the file with the result.
+    ///
+    /// # Errors
+    /// Returns a `PrometheusError` if serialization or validation fails, or if writing or
+    /// renaming the file fails.
+    pub fn write<'s, T, L, Li>(
+        &self,
+        value: &T,
+        namespace: Option<&'s str>,
+        metadata: &'s HashMap<&'static str, MetricDescriptor>,
+        common_labels: L,
+    ) -> Result<(), PrometheusError>
+    where
+        T: ?Sized + Serialize,
+        L: IntoIterator<Item = Li>,
+        Li: Borrow<(&'s str, &'s str)>,
+    {
+        let mut buf = Vec::new();
+        write_prometheus_text(value, &mut buf, namespace, metadata, common_labels)?;
+        if self.validate {
+            validate_exposition(&String::from_utf8_lossy(&buf))?;
+        }
+
+        let tmp_path = self.tmp_path();
+        let result = self.write_tmp(&tmp_path, &buf).and_then(|()| {
+            fs::rename(&tmp_path, &self.path)?;
+            Ok(())
+        });
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result?;
+        // Persist the rename. Not all platforms support syncing directories.
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            let _ = File::open(dir).and_then(|dir| dir.sync_all());
+        }
+        Ok(())
+    }
+
+    /// Path of a new temporary file, a hidden file next to the target without the `.prom`
+    /// extension so the collector ignores it. Each write uses its own file, so concurrent
+    /// writes never share one.
+    fn tmp_path(&self) -> PathBuf {
+        let file_name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let id = TMP_FILES.fetch_add(1, Ordering::Relaxed);
+        self.path
+            .with_file_name(format!(".{file_name}.{}.{id}.tmp", process::id()))
+    }
+
+    fn write_tmp(&self, tmp_path: &Path, contents: &[u8]) -> std::io::Result<()> {
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        let mut file = options.open(tmp_path)?;
+        #[cfg(unix)]
+        if let Some(mode) = self.mode {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(mode))?;
+        }
+        file.write_all(contents)?;
+        file.sync_all()
+    }
+}
+
+/// Checks that `text` is well-formed Prometheus text exposition.
+pub(crate) fn validate_exposition(text: &str) -> Result<(), PrometheusError> {
+    for (i, line) in text.lines().enumerate() {
+        validate_line(line).map_err(|reason| PrometheusError::InvalidExposition {
+            line: i + 1,
+            reason: reason.to_owned(),
+        })?;
+    }
+    Ok(())
+}
+
+fn validate_line(line: &str) -> Result<(), &'static str> {
+    if line.is_empty() {
+        return Ok(());
+    }
+    if let Some(comment) = line.strip_prefix('#') {
+        let mut parts = comment.split_whitespace();
+        return match parts.next() {
+            Some("HELP") => parts
+                .next()
+                .filter(|name| is_metric_name(name))
+                .map(|_| ())
+                .ok_or("invalid metric name in HELP"),
+            Some("TYPE") => {
+                if !parts.next().is_some_and(is_metric_name) {
+                    return Err("invalid metric name in TYPE");
+                }
+                match parts.next() {
+                    Some("counter" | "gauge" | "histogram" | "summary" | "untyped") => Ok(()),
+                    _ => Err("invalid metric type"),
+                }
+            }
+            _ => Ok(()),
+        };
+    }
+
+    let name_end = line.find(['{', ' ']).ok_or("missing value")?;
+    if !is_metric_name(&line[..name_end]) {
+        return Err("invalid metric name");
+    }
+    let mut rest = &line[name_end..];
+    if let Some(labels) = rest.strip_prefix('{') {
+        rest = skip_labels(labels)?;
+    }
+    let mut parts = rest.split_whitespace();
+    let value = parts.next().ok_or("missing value")?;
+    if !matches!(value, "+Inf" | "-Inf" | "NaN") && value.parse::<f64>().is_err() {
+        return Err("invalid value");
+    }
+    if let Some(timestamp) = parts.next()
+        && timestamp.parse::<i64>().is_err()
+    {
+        return Err("invalid timestamp");
+    }
+    if parts.next().is_some() {
+        return Err("unexpected trailing content");
+    }
+    Ok(())
+}
+
+/// Skips a label set after its opening brace, returning what follows the closing brace.
+fn skip_labels(mut rest: &str) -> Result<&str, &'static str> {
+    loop {
+        rest = rest.trim_start();
+        if let Some(rest) = rest.strip_prefix('}') {
+            return Ok(rest);
+        }
+        let name_end = rest.find('=').ok_or("invalid label")?;
+        if !is_label_name(rest[..name_end].trim_end()) {
+            return Err("invalid label name");
+        }
+        rest = rest[name_end + 1..]
+            .trim_start()
+            .strip_prefix('"')
+            .ok_or("label value must be quoted")?;
+        let mut escaped = false;
+        let value_end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                let end = c == '"' && !escaped;
+                escaped = c == '\\' && !escaped;
+                end
+            })
+            .map(|(i, _)| i)
+            .ok_or("unterminated label value")?;
+        rest = rest[value_end + 1..].trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest);
+    }
+}
+
+fn is_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}